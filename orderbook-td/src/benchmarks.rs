@@ -1,4 +1,6 @@
 use crate::interfaces::{OrderBook, Side, Update};
+use crate::orderbook::OrderBookImpl;
+use crate::workload::{LevelSpacing, WorkloadGenerator};
 use std::time::Instant;
 use std::hint::black_box;
 
@@ -7,18 +9,42 @@ use std::hint::black_box;
 // ============================================================================
 
 const BATCH: u64 = 1000; // amortize Instant::now() noise
+const COLD_PRICE_RANGE: u64 = 200_000; // span of the full price array
+const COLD_STRIDE: u64 = 104_729; // large prime, scatters consecutive reads across the range
+const TOP_LEVELS_DEPTH: usize = 10;
+const CONSTRUCTIONS: usize = 100;
+const IN_PLACE_LEVELS: usize = 32; // resting levels per side resized by the in-place benchmark
+const SCATTERED_LEVELS: u64 = 100_000; // resting levels spread over the range by the scattered benchmark
+const SWEEP_SEED: u64 = 42;
+const SWEEP_MEAN_GAP: f64 = 4.0; // ticks between levels in the density sweep; 10k levels span 40k ticks
 #[derive(Debug, Clone)]
 pub struct BenchmarkResult {
     pub name: String,
     pub avg_update_ns: f64,
+    pub avg_in_place_update_ns: f64,
     pub avg_spread_ns: f64,
     pub avg_best_bid_ns: f64,
     pub avg_best_ask_ns: f64,
     pub avg_random_read_ns: f64,
+    pub avg_cold_read_ns: f64,
+    pub avg_top_levels_ns: f64,
+    pub avg_construction_ns: f64,
     pub p50_update_ns: f64,
     pub p95_update_ns: f64,
     pub p99_update_ns: f64,
     pub total_operations: usize,
+    /// Active levels per side the book was populated with, for density sweeps
+    pub density: Option<usize>,
+}
+
+/// Per-update cost of one scattered update stream applied two ways
+#[derive(Debug, Clone)]
+pub struct ScatteredBatchResult {
+    /// One `apply_update` call per update
+    pub avg_single_ns: f64,
+    /// `OrderBookImpl::apply_batch`, which prefetches upcoming level slots
+    pub avg_batch_ns: f64,
+    pub total_operations: usize,
 }
 
 pub struct OrderBookBenchmark;
@@ -31,31 +57,94 @@ impl OrderBookBenchmark {
 
         let update_timings = Self::benchmark_updates(&mut ob, iterations);
 
-        let spread_timings = Self::benchmark_spread(&ob, iterations / 10);
-        let best_bid_timings = Self::benchmark_best_bid(&ob, iterations / 10);
-        let best_ask_timings = Self::benchmark_best_ask(&ob, iterations / 10);
-        let read_timings = Self::benchmark_random_reads(&ob, iterations / 10);
+        Self::collect_results(name, &mut ob, update_timings, iterations)
+    }
+
+    /// Benchmark against a generated book shape and update stream instead of the
+    /// dense fixed preset, so gaps between levels show up in the numbers
+    pub fn run_with_workload<T: OrderBook>(
+        name: &str,
+        workload: &mut WorkloadGenerator,
+        iterations: usize,
+    ) -> BenchmarkResult {
+        let mut ob = T::new();
+        workload.populate(&mut ob);
+
+        let update_timings = Self::benchmark_update_stream(&mut ob, workload, iterations);
+
+        Self::collect_results(name, &mut ob, update_timings, iterations)
+    }
+
+    /// `run_with_workload` once per density (active levels per side), each on
+    /// a fresh book with geometrically spaced levels, so the results show how
+    /// update and read costs grow with book size
+    pub fn run_density_sweep<T: OrderBook>(
+        name: &str,
+        densities: &[usize],
+        iterations: usize,
+    ) -> Vec<BenchmarkResult> {
+        densities
+            .iter()
+            .map(|&density| {
+                let mut workload = WorkloadGenerator::new(
+                    SWEEP_SEED,
+                    density,
+                    LevelSpacing::Geometric {
+                        mean_gap: SWEEP_MEAN_GAP,
+                    },
+                );
+                let mut result = Self::run_with_workload::<T>(name, &mut workload, iterations);
+                result.density = Some(density);
+                result
+            })
+            .collect()
+    }
+
+    fn collect_results<T: OrderBook>(
+        name: &str,
+        ob: &mut T,
+        update_timings: Vec<f64>,
+        iterations: usize,
+    ) -> BenchmarkResult {
+        let in_place_timings = Self::benchmark_in_place_updates(ob, iterations / 10);
+        let ob = &*ob;
+        let spread_timings = Self::benchmark_spread(ob, iterations / 10);
+        let best_bid_timings = Self::benchmark_best_bid(ob, iterations / 10);
+        let best_ask_timings = Self::benchmark_best_ask(ob, iterations / 10);
+        let read_timings = Self::benchmark_random_reads(ob, iterations / 10);
+        let cold_read_timings = Self::benchmark_cold_reads(ob, iterations / 10);
+        let top_levels_timings = Self::benchmark_top_levels(ob, iterations / 100);
+        let construction_timings = Self::benchmark_construction::<T>(CONSTRUCTIONS);
 
         let avg_update = Self::average(&update_timings);
+        let avg_in_place_update = Self::average(&in_place_timings);
         let avg_spread = Self::average(&spread_timings);
         let avg_best_bid = Self::average(&best_bid_timings);
         let avg_best_ask = Self::average(&best_ask_timings);
         let avg_read = Self::average(&read_timings);
+        let avg_cold_read = Self::average(&cold_read_timings);
+        let avg_top_levels = Self::average(&top_levels_timings);
+        let avg_construction = Self::average(&construction_timings);
 
-        let mut sorted_updates = update_timings.clone();
+        let mut sorted_updates = update_timings;
         sorted_updates.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
         BenchmarkResult {
             name: name.to_string(),
             avg_update_ns: avg_update,
+            avg_in_place_update_ns: avg_in_place_update,
             avg_spread_ns: avg_spread,
             avg_best_bid_ns: avg_best_bid,
             avg_best_ask_ns: avg_best_ask,
             avg_random_read_ns: avg_read,
+            avg_cold_read_ns: avg_cold_read,
+            avg_top_levels_ns: avg_top_levels,
+            avg_construction_ns: avg_construction,
             p50_update_ns: sorted_updates[sorted_updates.len() / 2],
             p95_update_ns: sorted_updates[sorted_updates.len() * 95 / 100],
             p99_update_ns: sorted_updates[sorted_updates.len() * 99 / 100],
             total_operations: iterations,
+            density: None,
         }
     }
 
@@ -90,7 +179,79 @@ impl OrderBookBenchmark {
 
             let start = Instant::now();
             for _ in 0..BATCH {
-                black_box(ob.apply_update(update.clone()));
+                ob.apply_update(black_box(update.clone()));
+            }
+            let elapsed = start.elapsed().as_nanos() as f64;
+
+            timings.push(elapsed / BATCH as f64);
+        }
+
+        timings
+    }
+
+    // =========================================================================
+    // BENCHMARK IN-PLACE UPDATES
+    // =========================================================================
+    // The most common feed message: a new size for a level that is already
+    // resting behind the touch, so no level appears or vanishes and neither
+    // best moves. Sizes alternate between two values per level so the
+    // unchanged-quantity shortcut never fires
+    fn benchmark_in_place_updates<T: OrderBook>(ob: &mut T, iterations: usize) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations);
+
+        let mut targets = Vec::with_capacity(4 * IN_PLACE_LEVELS);
+        for bump in [1, 2] {
+            for side in [Side::Bid, Side::Ask] {
+                let behind_touch = ob.get_top_levels(side, IN_PLACE_LEVELS + 1);
+                for (price, quantity) in behind_touch.into_iter().skip(1) {
+                    targets.push(Update::Set {
+                        price,
+                        quantity: quantity + bump,
+                        side,
+                    });
+                }
+            }
+        }
+        if targets.is_empty() {
+            return vec![0.0];
+        }
+
+        let mut next = 0;
+        for _ in 0..iterations {
+            let start = Instant::now();
+            for _ in 0..BATCH {
+                ob.apply_update(black_box(targets[next].clone()));
+                next += 1;
+                if next == targets.len() {
+                    next = 0;
+                }
+            }
+            let elapsed = start.elapsed().as_nanos() as f64;
+            timings.push(elapsed / BATCH as f64);
+        }
+
+        timings
+    }
+
+    // =========================================================================
+    // BENCHMARK UPDATE STREAM
+    // =========================================================================
+    // Each batch applies BATCH distinct generated updates, so removals at the
+    // touch really do trigger best-price rescans instead of repeating a no-op
+    fn benchmark_update_stream<T: OrderBook>(
+        ob: &mut T,
+        workload: &mut WorkloadGenerator,
+        iterations: usize,
+    ) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations);
+        let mut batch = Vec::with_capacity(BATCH as usize);
+
+        for _ in 0..iterations {
+            workload.fill_updates(&mut batch, BATCH as usize);
+
+            let start = Instant::now();
+            for update in batch.drain(..) {
+                ob.apply_update(update);
             }
             let elapsed = start.elapsed().as_nanos() as f64;
 
@@ -176,6 +337,154 @@ impl OrderBookBenchmark {
         timings
     }
 
+    // =========================================================================
+    // BENCHMARK COLD READS
+    // =========================================================================
+    // Unlike random reads, every read in a batch lands on a different price
+    // scattered over the whole range, so the working set never fits in cache
+    fn benchmark_cold_reads<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations);
+        let mut seed: u64 = 0;
+
+        for _ in 0..iterations {
+            let start = Instant::now();
+            for _ in 0..BATCH {
+                seed += 1;
+                let price = (seed * COLD_STRIDE % COLD_PRICE_RANGE) as i64;
+                let side = if seed.is_multiple_of(2) {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                };
+                black_box(ob.get_quantity_at(black_box(price), side));
+            }
+            let elapsed = start.elapsed().as_nanos() as f64;
+            timings.push(elapsed / BATCH as f64);
+        }
+
+        timings
+    }
+
+    // =========================================================================
+    // BENCHMARK TOP LEVELS
+    // =========================================================================
+    fn benchmark_top_levels<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations);
+
+        for i in 0..iterations {
+            let side = if i % 2 == 0 { Side::Bid } else { Side::Ask };
+
+            let start = Instant::now();
+            for _ in 0..BATCH {
+                black_box(ob.get_top_levels(side, TOP_LEVELS_DEPTH));
+            }
+            let elapsed = start.elapsed().as_nanos() as f64;
+            timings.push(elapsed / BATCH as f64);
+        }
+
+        timings
+    }
+
+    // =========================================================================
+    // BENCHMARK CONSTRUCTION
+    // =========================================================================
+    // Time T::new() plus one update, so lazily zeroed pages are faulted in for
+    // the touched slot the same way the first real update would
+    fn benchmark_construction<T: OrderBook>(iterations: usize) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations);
+
+        for _ in 0..iterations {
+            let start = Instant::now();
+            let mut ob = T::new();
+            ob.apply_update(black_box(Update::Set {
+                price: 100000,
+                quantity: 1,
+                side: Side::Bid,
+            }));
+            black_box(&ob);
+            let elapsed = start.elapsed().as_nanos() as f64;
+            drop(ob);
+            timings.push(elapsed);
+        }
+
+        timings
+    }
+
+    // =========================================================================
+    // BENCHMARK SCATTERED BATCH
+    // =========================================================================
+    // Resize levels spread over the whole price range, in batches of BATCH
+    // updates, once with a plain apply_update loop and once through apply_batch.
+    // Consecutive updates land far apart, so most miss cache unless prefetched
+    pub fn run_scattered_batch(iterations: usize) -> ScatteredBatchResult {
+        let mut ob = OrderBookImpl::empty();
+        let levels: Vec<(i64, Side)> = (0..SCATTERED_LEVELS)
+            .map(|i| {
+                let price = (i * COLD_STRIDE % COLD_PRICE_RANGE) as i64;
+                let side = if price < COLD_PRICE_RANGE as i64 / 2 {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                };
+                (price, side)
+            })
+            .collect();
+        for &(price, side) in &levels {
+            ob.apply_update(Update::Set {
+                price,
+                quantity: 100,
+                side,
+            });
+        }
+
+        let mut single = Vec::with_capacity(iterations);
+        let mut batched = Vec::with_capacity(iterations);
+        let mut seed: u64 = 0;
+        // Each pass gets its own batch, so neither runs on levels the other
+        // just pulled into cache
+        let mut next_batch = || -> Vec<Update> {
+            (0..BATCH)
+                .map(|_| {
+                    seed += 1;
+                    let (price, side) = levels[(seed * COLD_STRIDE % SCATTERED_LEVELS) as usize];
+                    Update::Set {
+                        price,
+                        quantity: 100 + seed % 50,
+                        side,
+                    }
+                })
+                .collect()
+        };
+        for _ in 0..iterations {
+            let updates = next_batch();
+            let start = Instant::now();
+            ob.apply_batch(black_box(&updates));
+            batched.push(start.elapsed().as_nanos() as f64 / BATCH as f64);
+
+            let updates = next_batch();
+            let start = Instant::now();
+            for update in updates {
+                ob.apply_update(update);
+            }
+            single.push(start.elapsed().as_nanos() as f64 / BATCH as f64);
+        }
+
+        ScatteredBatchResult {
+            avg_single_ns: Self::average(&single),
+            avg_batch_ns: Self::average(&batched),
+            total_operations: iterations * BATCH as usize,
+        }
+    }
+
+    pub fn print_scattered_batch(result: &ScatteredBatchResult) {
+        println!("\n{}", "=".repeat(60));
+        println!("  SCATTERED BATCH: {} updates", result.total_operations);
+        println!("{}", "=".repeat(60));
+        println!("  apply_update loop: {:.3} ns", result.avg_single_ns);
+        println!("  apply_batch:       {:.3} ns", result.avg_batch_ns);
+        println!("{}", "=".repeat(60));
+    }
+
     // =========================================================================
     // STATS
     // =========================================================================
@@ -188,17 +497,56 @@ impl OrderBookBenchmark {
         println!("  BENCHMARK RESULTS: {}", result.name);
         println!("{}", "=".repeat(60));
         println!("  Total Operations: {}", result.total_operations);
+        if let Some(density) = result.density {
+            println!("  Density:          {} levels/side", density);
+        }
         println!("  ---");
         println!("  Update Operations:");
         println!("    Average: {:.3} ns", result.avg_update_ns);
         println!("    P50:     {:.3} ns", result.p50_update_ns);
         println!("    P95:     {:.3} ns", result.p95_update_ns);
         println!("    P99:     {:.3} ns", result.p99_update_ns);
+        println!("  In-place:       {:.3} ns", result.avg_in_place_update_ns);
         println!("  ---");
         println!("  Get Best Bid:   {:.3} ns", result.avg_best_bid_ns);
         println!("  Get Best Ask:   {:.3} ns", result.avg_best_ask_ns);
         println!("  Get Spread:     {:.3} ns", result.avg_spread_ns);
         println!("  Random Reads:   {:.3} ns", result.avg_random_read_ns);
+        println!("  Cold Reads:     {:.3} ns", result.avg_cold_read_ns);
+        println!(
+            "  Top {} Levels:  {:.3} ns",
+            TOP_LEVELS_DEPTH, result.avg_top_levels_ns
+        );
+        println!("  Construction:   {:.3} ns", result.avg_construction_ns);
         println!("{}", "=".repeat(60));
     }
+
+    /// One row per density with the main latencies, plus the average update
+    /// cost relative to the first (sparsest listed) density
+    pub fn print_density_sweep(results: &[BenchmarkResult]) {
+        let Some(first) = results.first() else {
+            return;
+        };
+        println!("\n{}", "=".repeat(76));
+        println!("  DENSITY SWEEP: {}", first.name);
+        println!("{}", "=".repeat(76));
+        println!(
+            "  {:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+            "levels", "update", "p99", "in-place", "read", "top 10", "scaling"
+        );
+        for result in results {
+            println!(
+                "  {:>8} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>7.2}x",
+                result.density.map_or("-".to_string(), |d| d.to_string()),
+                result.avg_update_ns,
+                result.p99_update_ns,
+                result.avg_in_place_update_ns,
+                result.avg_random_read_ns,
+                result.avg_top_levels_ns,
+                result.avg_update_ns / first.avg_update_ns
+            );
+        }
+        println!("  (ns per operation)");
+        println!("{}", "=".repeat(76));
+    }
 }
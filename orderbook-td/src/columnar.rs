@@ -0,0 +1,92 @@
+use crate::interfaces::Side;
+use crate::orderbook::OrderBookImpl;
+use arrow_array::{Int64Array, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use std::sync::Arc;
+
+// ============================================================================
+// COLUMNAR EXPORT – ARROW RECORD BATCHES FOR ANALYTICS PIPELINES
+// ============================================================================
+// Built with the `arrow` feature. One batch per side with non-nullable
+// `price: Int64` and `quantity: UInt64` columns, rows in `all_levels` order
+// (best first), so it loads straight into DataFusion or Polars.
+
+/// Schema shared by every batch `to_record_batch` produces
+pub fn level_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("price", DataType::Int64, false),
+        Field::new("quantity", DataType::UInt64, false),
+    ])
+}
+
+impl OrderBookImpl {
+    /// Populated levels on a side as an Arrow batch, best first
+    /// Rows match `all_levels(side)` exactly; an empty side yields zero rows
+    pub fn to_record_batch(&self, side: Side) -> RecordBatch {
+        let count = self.level_count(side);
+        let mut prices = Vec::with_capacity(count);
+        let mut quantities = Vec::with_capacity(count);
+        for (price, quantity) in self.levels(side) {
+            prices.push(price);
+            quantities.push(quantity);
+        }
+        RecordBatch::try_new(
+            Arc::new(level_schema()),
+            vec![
+                Arc::new(Int64Array::from(prices)),
+                Arc::new(UInt64Array::from(quantities)),
+            ],
+        )
+        .expect("columns match the level schema")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::{OrderBook, Update};
+
+    #[test]
+    fn record_batch_matches_all_levels() {
+        let mut ob = OrderBookImpl::new();
+        for (price, quantity, side) in [
+            (98, 3, Side::Bid),
+            (100, 5, Side::Bid),
+            (99, 0, Side::Bid),
+            (103, 7, Side::Ask),
+            (101, 2, Side::Ask),
+        ] {
+            ob.apply_update(Update::Set {
+                price,
+                quantity,
+                side,
+            });
+        }
+
+        for side in [Side::Bid, Side::Ask] {
+            let batch = ob.to_record_batch(side);
+            assert_eq!(batch.schema().as_ref(), &level_schema());
+            let prices = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            let quantities = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap();
+            let rows: Vec<_> = prices
+                .values()
+                .iter()
+                .copied()
+                .zip(quantities.values().iter().copied())
+                .collect();
+            assert_eq!(rows, ob.all_levels(side));
+        }
+
+        let empty = OrderBookImpl::new().to_record_batch(Side::Ask);
+        assert_eq!(empty.num_rows(), 0);
+        assert_eq!(empty.num_columns(), 2);
+    }
+}
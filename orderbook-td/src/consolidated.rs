@@ -0,0 +1,51 @@
+use crate::interfaces::{Price, Quantity};
+use crate::orderbook::OrderBookImpl;
+
+// ============================================================================
+// CONSOLIDATED VIEW – NBBO ACROSS INDEPENDENT BOOKS
+// ============================================================================
+
+/// Read-only view over several books (e.g. one per venue) reporting the best
+/// bid and ask across all of them, with the index of the book quoting it
+/// Only each book's cached best is read; nothing is merged or copied
+pub struct ConsolidatedView<'a> {
+    books: Vec<&'a OrderBookImpl>,
+}
+
+impl<'a> ConsolidatedView<'a> {
+    pub fn new(books: Vec<&'a OrderBookImpl>) -> Self {
+        ConsolidatedView { books }
+    }
+
+    pub fn books(&self) -> &[&'a OrderBookImpl] {
+        &self.books
+    }
+
+    /// Highest bid across all books as (book index, price, quantity)
+    /// On a tie the lowest book index wins
+    pub fn best_bid(&self) -> Option<(usize, Price, Quantity)> {
+        let mut best: Option<(usize, Price, Quantity)> = None;
+        for (index, book) in self.books.iter().enumerate() {
+            if let Some((price, qty)) = book.get_best_bid_with_qty()
+                && best.is_none_or(|(_, best_price, _)| price > best_price)
+            {
+                best = Some((index, price, qty));
+            }
+        }
+        best
+    }
+
+    /// Lowest ask across all books as (book index, price, quantity)
+    /// On a tie the lowest book index wins
+    pub fn best_ask(&self) -> Option<(usize, Price, Quantity)> {
+        let mut best: Option<(usize, Price, Quantity)> = None;
+        for (index, book) in self.books.iter().enumerate() {
+            if let Some((price, qty)) = book.get_best_ask_with_qty()
+                && best.is_none_or(|(_, best_price, _)| price < best_price)
+            {
+                best = Some((index, price, qty));
+            }
+        }
+        best
+    }
+}
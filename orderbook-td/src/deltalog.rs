@@ -0,0 +1,176 @@
+use crate::interfaces::{Price, Side, Update};
+use std::io::{self, Read, Write};
+
+// ============================================================================
+// DELTA LOG – COMPACT ARCHIVAL ENCODING OF UPDATE STREAMS
+// ============================================================================
+// Record layout:
+//   header   1 byte   bit 0 = side (0 bid, 1 ask), bit 1 = kind (0 Set, 1 Remove)
+//   price    varint   zigzag(price - previous record's price), first record vs 0
+//   quantity varint   Set records only
+// Consecutive updates usually touch nearby prices, so most records fit in 3-4 bytes.
+
+const SIDE_ASK: u8 = 0b01;
+const KIND_REMOVE: u8 = 0b10;
+
+#[inline]
+fn zigzag(delta: i64) -> u64 {
+    ((delta << 1) ^ (delta >> 63)) as u64
+}
+
+#[inline]
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Writes updates in the delta log format
+pub struct DeltaLogWriter<W: Write> {
+    inner: W,
+    last_price: Price,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> DeltaLogWriter<W> {
+    pub fn new(inner: W) -> Self {
+        DeltaLogWriter {
+            inner,
+            last_price: 0,
+            buf: Vec::with_capacity(21),
+        }
+    }
+
+    pub fn write(&mut self, update: &Update) -> io::Result<()> {
+        let (price, quantity, side, header) = match *update {
+            Update::Set {
+                price,
+                quantity,
+                side,
+            } => (price, Some(quantity), side, 0),
+            Update::Remove { price, side } => (price, None, side, KIND_REMOVE),
+        };
+        let header = match side {
+            Side::Bid => header,
+            Side::Ask => header | SIDE_ASK,
+        };
+
+        self.buf.clear();
+        self.buf.push(header);
+        Self::push_varint(&mut self.buf, zigzag(price.wrapping_sub(self.last_price)));
+        if let Some(quantity) = quantity {
+            Self::push_varint(&mut self.buf, quantity);
+        }
+        self.last_price = price;
+
+        self.inner.write_all(&self.buf)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    #[inline]
+    fn push_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+}
+
+/// Reads back a stream produced by `DeltaLogWriter`
+/// Yields `Err(UnexpectedEof)` if the stream ends in the middle of a record
+pub struct DeltaLogReader<R: Read> {
+    inner: R,
+    last_price: Price,
+}
+
+impl<R: Read> DeltaLogReader<R> {
+    pub fn new(inner: R) -> Self {
+        DeltaLogReader {
+            inner,
+            last_price: 0,
+        }
+    }
+
+    /// Next update, or Ok(None) at a clean end of stream
+    pub fn read_update(&mut self) -> io::Result<Option<Update>> {
+        let header = match self.read_byte()? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        if header & !(SIDE_ASK | KIND_REMOVE) != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid delta log header",
+            ));
+        }
+
+        let side = if header & SIDE_ASK != 0 {
+            Side::Ask
+        } else {
+            Side::Bid
+        };
+        let price = self.last_price.wrapping_add(unzigzag(self.read_varint()?));
+        self.last_price = price;
+
+        if header & KIND_REMOVE != 0 {
+            Ok(Some(Update::Remove { price, side }))
+        } else {
+            let quantity = self.read_varint()?;
+            Ok(Some(Update::Set {
+                price,
+                quantity,
+                side,
+            }))
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.inner.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self
+                .read_byte()?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            // The 10th byte holds only bit 63; anything above it can't fit
+            if shift == 63 && byte & 0x7e != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "varint longer than 64 bits",
+                ));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varint longer than 64 bits",
+        ))
+    }
+}
+
+impl<R: Read> Iterator for DeltaLogReader<R> {
+    type Item = io::Result<Update>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_update().transpose()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::interfaces::{OrderBook, Price, Side};
+use crate::orderbook::OrderBookImpl;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+// ============================================================================
+// C API – OPAQUE BOOK HANDLE FOR NON-RUST CONSUMERS
+// ============================================================================
+// Built with the `ffi` feature. The book is an opaque pointer owned by the
+// caller between `ob_book_new` and `ob_book_free`. No function unwinds across
+// the boundary: panics are caught and reported as a failure value. Prices
+// outside [0, ob_book_max_price(book)) are rejected rather than reaching the
+// unchecked array accessors.
+
+pub const OB_SIDE_BID: u8 = 0;
+pub const OB_SIDE_ASK: u8 = 1;
+
+/// Returned by `ob_book_spread` when either side is empty
+pub const OB_NO_SPREAD: i64 = i64::MIN;
+
+fn side_from(side: u8) -> Option<Side> {
+    match side {
+        OB_SIDE_BID => Some(Side::Bid),
+        OB_SIDE_ASK => Some(Side::Ask),
+        _ => None,
+    }
+}
+
+/// New empty book, or null if allocation panicked
+#[unsafe(no_mangle)]
+pub extern "C" fn ob_book_new() -> *mut OrderBookImpl {
+    catch_unwind(|| Box::into_raw(Box::new(OrderBookImpl::new()))).unwrap_or(std::ptr::null_mut())
+}
+
+/// Free a book returned by `ob_book_new`. Null is ignored
+///
+/// # Safety
+/// `book` must be null or a pointer from `ob_book_new` not already freed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_free(book: *mut OrderBookImpl) {
+    if !book.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(book) })));
+    }
+}
+
+/// Exclusive upper bound on prices `book` accepts, or -1 if `book` is null
+///
+/// # Safety
+/// `book` must be null or a live pointer from `ob_book_new`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_max_price(book: *const OrderBookImpl) -> i64 {
+    read_book(book, -1, |book| book.max_price())
+}
+
+/// Set a level (quantity 0 removes it). Returns false, leaving the book
+/// untouched, for a null book, an unknown side or an out-of-range price
+///
+/// # Safety
+/// `book` must be null or a live pointer from `ob_book_new`, not used
+/// concurrently from another thread
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_set(
+    book: *mut OrderBookImpl,
+    price: i64,
+    quantity: u64,
+    side: u8,
+) -> bool {
+    with_book_mut(book, price, side, |book, side| {
+        book.set(price, quantity, side)
+    })
+}
+
+/// Remove a level. Same failure cases as `ob_book_set`
+///
+/// # Safety
+/// Same as `ob_book_set`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_remove(book: *mut OrderBookImpl, price: i64, side: u8) -> bool {
+    with_book_mut(book, price, side, |book, side| book.remove(price, side))
+}
+
+/// Best bid price, or -1 if the bid side is empty or `book` is null
+///
+/// # Safety
+/// `book` must be null or a live pointer from `ob_book_new`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_best_bid(book: *const OrderBookImpl) -> i64 {
+    read_book(book, -1, |book| book.raw_best_bid())
+}
+
+/// Best ask price, or -1 if the ask side is empty or `book` is null
+///
+/// # Safety
+/// `book` must be null or a live pointer from `ob_book_new`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_best_ask(book: *const OrderBookImpl) -> i64 {
+    read_book(book, -1, |book| book.raw_best_ask())
+}
+
+/// Best ask minus best bid, or `OB_NO_SPREAD` if either side is empty
+///
+/// # Safety
+/// `book` must be null or a live pointer from `ob_book_new`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ob_book_spread(book: *const OrderBookImpl) -> i64 {
+    read_book(book, OB_NO_SPREAD, |book| {
+        book.get_spread().unwrap_or(OB_NO_SPREAD)
+    })
+}
+
+fn with_book_mut(
+    book: *mut OrderBookImpl,
+    price: Price,
+    side: u8,
+    write: impl FnOnce(&mut OrderBookImpl, Side),
+) -> bool {
+    let Some(side) = side_from(side) else {
+        return false;
+    };
+    // SAFETY: the caller guarantees `book` is null or live and unaliased
+    let Some(book) = (unsafe { book.as_mut() }) else {
+        return false;
+    };
+    if !(0..book.max_price()).contains(&price) {
+        return false;
+    }
+    catch_unwind(AssertUnwindSafe(|| write(book, side))).is_ok()
+}
+
+fn read_book(
+    book: *const OrderBookImpl,
+    fallback: i64,
+    read: impl FnOnce(&OrderBookImpl) -> i64,
+) -> i64 {
+    // SAFETY: the caller guarantees `book` is null or live
+    match unsafe { book.as_ref() } {
+        Some(book) => catch_unwind(AssertUnwindSafe(|| read(book))).unwrap_or(fallback),
+        None => fallback,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_round_trip() {
+        unsafe {
+            let book = ob_book_new();
+            assert!(!book.is_null());
+            assert_eq!(ob_book_best_bid(book), -1);
+            assert_eq!(ob_book_spread(book), OB_NO_SPREAD);
+
+            assert!(ob_book_set(book, 100, 5, OB_SIDE_BID));
+            assert!(ob_book_set(book, 104, 3, OB_SIDE_ASK));
+            assert_eq!(ob_book_best_bid(book), 100);
+            assert_eq!(ob_book_best_ask(book), 104);
+            assert_eq!(ob_book_spread(book), 4);
+
+            // Bad input is rejected without touching the book
+            let max = ob_book_max_price(book);
+            assert!(!ob_book_set(book, max, 1, OB_SIDE_BID));
+            assert!(!ob_book_set(book, -5, 1, OB_SIDE_ASK));
+            assert!(!ob_book_set(book, 101, 1, 7));
+            assert!(!ob_book_set(std::ptr::null_mut(), 101, 1, OB_SIDE_BID));
+            assert_eq!(ob_book_best_bid(book), 100);
+
+            assert!(ob_book_remove(book, 100, OB_SIDE_BID));
+            assert_eq!(ob_book_best_bid(book), -1);
+            assert_eq!(ob_book_best_ask(std::ptr::null()), -1);
+
+            ob_book_free(book);
+            ob_book_free(std::ptr::null_mut());
+        }
+    }
+}
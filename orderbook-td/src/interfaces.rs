@@ -1,3 +1,6 @@
+use std::fmt::Debug;
+use std::ops::{Add, Sub};
+
 // ============================================================================
 // ORDERBOOK COMPETITION TEMPLATE
 // ============================================================================
@@ -12,15 +15,122 @@ pub type Price = i64;
 /// Quantity in the orderbook
 pub type Quantity = u64;
 
+/// Arithmetic the book needs from a quantity type
+/// `ZERO` means "no level": presence bits are driven by `is_zero`, so any
+/// other value (including a tiny float) is a populated level
+pub trait QuantityLike:
+    Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Debug + Send + Sync + 'static
+{
+    /// Accumulator for price * quantity, wide enough not to overflow
+    type Notional: Copy
+        + Add<Output = Self::Notional>
+        + Sub<Output = Self::Notional>
+        + PartialEq
+        + Debug
+        + Send
+        + Sync;
+
+    const ZERO: Self;
+    const ZERO_NOTIONAL: Self::Notional;
+
+    fn is_zero(self) -> bool;
+
+    fn to_f64(self) -> f64;
+
+    fn notional(self, price: Price) -> Self::Notional;
+
+    /// Sums for side totals and block sums: these wrap on integer types so a
+    /// side holding more than `MAX` in total reads modulo `MAX + 1` instead of
+    /// panicking; `sanitize_stream` rejects updates that would get there
+    fn wrapping_add(self, other: Self) -> Self;
+
+    fn wrapping_sub(self, other: Self) -> Self;
+}
+
+macro_rules! integer_quantity {
+    ($($ty:ty),*) => {$(
+        impl QuantityLike for $ty {
+            type Notional = u128;
+
+            const ZERO: Self = 0;
+            const ZERO_NOTIONAL: u128 = 0;
+
+            #[inline(always)]
+            fn is_zero(self) -> bool {
+                self == 0
+            }
+
+            #[inline(always)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            #[inline(always)]
+            fn notional(self, price: Price) -> u128 {
+                price as u128 * self as u128
+            }
+
+            #[inline(always)]
+            fn wrapping_add(self, other: Self) -> Self {
+                <$ty>::wrapping_add(self, other)
+            }
+
+            #[inline(always)]
+            fn wrapping_sub(self, other: Self) -> Self {
+                <$ty>::wrapping_sub(self, other)
+            }
+        }
+    )*};
+}
+
+integer_quantity!(u32, u64);
+
+/// Floating quantities for venues with continuous sizes
+/// Totals, block sums and notional are running f64 sums, so after many
+/// updates they can drift from the exact sum of resting levels by rounding
+/// error (and a fully emptied side may report a tiny non-zero total).
+/// Level presence is exact: a level is empty only when set to 0.0 or removed
+impl QuantityLike for f64 {
+    type Notional = f64;
+
+    const ZERO: Self = 0.0;
+    const ZERO_NOTIONAL: f64 = 0.0;
+
+    #[inline(always)]
+    fn is_zero(self) -> bool {
+        self == 0.0
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    #[inline(always)]
+    fn notional(self, price: Price) -> f64 {
+        price as f64 * self
+    }
+
+    #[inline(always)]
+    fn wrapping_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    #[inline(always)]
+    fn wrapping_sub(self, other: Self) -> Self {
+        self - other
+    }
+}
+
 /// Side of the order
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Side {
     Bid,
     Ask,
 }
 
 /// Order book update operation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Update {
     /// Add or update a price level (price, quantity, side)
     /// If quantity is 0, this level should be removed
@@ -34,6 +144,63 @@ pub enum Update {
     Remove { price: Price, side: Side },
 }
 
+// Wire record header bits; the rest must be zero
+const WIRE_SIDE_ASK: u8 = 0b01;
+const WIRE_KIND_REMOVE: u8 = 0b10;
+
+impl Update {
+    /// Decode one wire record from the front of `buf`, big endian, returning
+    /// the update and the bytes consumed. Layout:
+    ///   header   u8    bit 0 = side (0 bid, 1 ask), bit 1 = kind (0 Set, 1 Remove)
+    ///   price    i64
+    ///   quantity u64   Set records only
+    /// Set records are 17 bytes, Remove records 9. Returns None if `buf` is
+    /// truncated or the header has unknown bits set
+    #[inline]
+    pub fn from_wire_be(buf: &[u8]) -> Option<(Update, usize)> {
+        Self::from_wire(buf, i64::from_be_bytes, u64::from_be_bytes)
+    }
+
+    /// `from_wire_be` with little-endian price and quantity
+    #[inline]
+    pub fn from_wire_le(buf: &[u8]) -> Option<(Update, usize)> {
+        Self::from_wire(buf, i64::from_le_bytes, u64::from_le_bytes)
+    }
+
+    #[inline(always)]
+    fn from_wire(
+        buf: &[u8],
+        price_from: fn([u8; 8]) -> i64,
+        quantity_from: fn([u8; 8]) -> u64,
+    ) -> Option<(Update, usize)> {
+        let (&header, rest) = buf.split_first()?;
+        if header & !(WIRE_SIDE_ASK | WIRE_KIND_REMOVE) != 0 {
+            return None;
+        }
+        let side = if header & WIRE_SIDE_ASK != 0 {
+            Side::Ask
+        } else {
+            Side::Bid
+        };
+        let (price, rest) = rest.split_first_chunk::<8>()?;
+        let price = price_from(*price);
+
+        if header & WIRE_KIND_REMOVE != 0 {
+            return Some((Update::Remove { price, side }, 9));
+        }
+        let (quantity, _) = rest.split_first_chunk::<8>()?;
+        let quantity = quantity_from(*quantity);
+        Some((
+            Update::Set {
+                price,
+                quantity,
+                side,
+            },
+            17,
+        ))
+    }
+}
+
 /// The main trait that students must implement
 pub trait OrderBook: Send + Sync {
     /// Create a new orderbook instance
@@ -43,8 +210,31 @@ pub trait OrderBook: Send + Sync {
 
     /// Apply an update to the orderbook
     /// This is the HOT PATH - optimize heavily!
+    ///
+    /// Out-of-range prices: a book indexes a fixed range of prices, and an
+    /// update priced outside it (including any negative price) must be
+    /// ignored, leaving the book unchanged. It must never panic or touch
+    /// memory outside the book. `get_quantity_at` reports None for such prices
     fn apply_update(&mut self, update: Update);
 
+    /// Set a level straight from raw fields, skipping `Update` construction
+    /// and matching. Must behave exactly like `apply_update(Update::Set { .. })`
+    #[inline(always)]
+    fn set(&mut self, price: Price, quantity: Quantity, side: Side) {
+        self.apply_update(Update::Set {
+            price,
+            quantity,
+            side,
+        });
+    }
+
+    /// Remove a level straight from raw fields
+    /// Must behave exactly like `apply_update(Update::Remove { .. })`
+    #[inline(always)]
+    fn remove(&mut self, price: Price, side: Side) {
+        self.apply_update(Update::Remove { price, side });
+    }
+
     /// Get the current spread (best_ask - best_bid)
     /// Returns None if either side is empty
     /// This is also HOT PATH
@@ -57,7 +247,7 @@ pub trait OrderBook: Send + Sync {
     fn get_best_ask(&self) -> Option<Price>;
 
     /// Get quantity at a specific price level
-    /// Returns None if the level doesn't exist
+    /// Returns None if the level doesn't exist or the price is out of range
     fn get_quantity_at(&self, price: Price, side: Side) -> Option<Quantity>;
 
     /// Get the top N levels on a given side
@@ -66,4 +256,216 @@ pub trait OrderBook: Send + Sync {
 
     /// Get total quantity across all levels for a side
     fn get_total_quantity(&self, side: Side) -> Quantity;
+
+    /// Quantity resting at prices strictly better than `price` on a side:
+    /// the queue a new order joining at `price` stands behind
+    /// Default probes every price between the touch and `price`
+    fn liquidity_ahead(&self, price: Price, side: Side) -> Quantity {
+        let better = match side {
+            Side::Bid => match self.get_best_bid() {
+                Some(best) if best > price => price + 1..=best,
+                _ => return 0,
+            },
+            Side::Ask => match self.get_best_ask() {
+                Some(best) if best < price => best..=price - 1,
+                _ => return 0,
+            },
+        };
+        better
+            .filter_map(|level| self.get_quantity_at(level, side))
+            .fold(0, Quantity::saturating_add)
+    }
+
+    /// Chance that a resting order at `price` fills if `expected_volume`
+    /// trades against its side
+    /// Default model is linear: the queue to clear is `liquidity_ahead` plus
+    /// the quantity already at `price`, and the probability grows with
+    /// `expected_volume / queue` up to 1. Returns 0 when `price` is behind the
+    /// touch, the side is empty, or no volume is expected
+    fn fill_probability(&self, price: Price, side: Side, expected_volume: Quantity) -> f64 {
+        let at_or_better = match side {
+            Side::Bid => self.get_best_bid().is_some_and(|best| price >= best),
+            Side::Ask => self.get_best_ask().is_some_and(|best| price <= best),
+        };
+        if !at_or_better || expected_volume == 0 {
+            return 0.0;
+        }
+        let queue = self
+            .liquidity_ahead(price, side)
+            .saturating_add(self.get_quantity_at(price, side).unwrap_or(0));
+        if queue == 0 {
+            return 1.0;
+        }
+        (expected_volume as f64 / queue as f64).min(1.0)
+    }
+
+    /// Cost of a market order taking `quantity` from `side`, best level first:
+    /// (quantity filled, notional = sum of price * quantity taken). Taking
+    /// from the asks prices a buy, from the bids a sell. The fill is partial
+    /// if the side runs out first; None if the side is empty. Notional
+    /// saturates at `u64::MAX`
+    /// Default re-fetches the top levels in doubling batches until covered
+    fn fill_cost(&self, side: Side, quantity: Quantity) -> Option<(Quantity, u64)> {
+        let mut n = 16;
+        loop {
+            let levels = self.get_top_levels(side, n);
+            let (filled, notional) = fill_from_levels(levels.iter().copied(), quantity)?;
+            if filled == quantity || levels.len() < n {
+                return Some((filled, notional));
+            }
+            n = n.saturating_mul(2);
+        }
+    }
+
+    /// Integer midpoint of the best bid and ask, rounded down
+    /// Returns None if either side is empty
+    #[inline]
+    fn mid_price(&self) -> Option<Price> {
+        let (bid, ask) = (self.get_best_bid()?, self.get_best_ask()?);
+        Some(bid + (ask - bid) / 2)
+    }
+
+    /// Size-weighted fair value of the touch:
+    ///   (best_ask_qty * best_bid + best_bid_qty * best_ask) / (best_bid_qty + best_ask_qty)
+    /// It leans toward the side with less resting size, the side more likely
+    /// to trade through next. Returns None if either side is empty
+    fn microprice(&self) -> Option<f64> {
+        let (bid, ask) = (self.get_best_bid()?, self.get_best_ask()?);
+        let bid_qty = self.get_quantity_at(bid, Side::Bid).unwrap_or(0);
+        let ask_qty = self.get_quantity_at(ask, Side::Ask).unwrap_or(0);
+        microprice_of(bid, bid_qty, ask, ask_qty)
+    }
+
+    /// Top-of-book imbalance (bid - ask) / (bid + ask) over the quantity in
+    /// the best `depth` levels of each side, in [-1, 1]: positive when bids
+    /// outweigh asks, +1 or -1 for a book with one side empty. None when
+    /// neither side has anything within `depth` (including `depth` 0), so a
+    /// balanced book (0.0) is distinguishable from no data
+    fn imbalance(&self, depth: usize) -> Option<f64> {
+        let sum = |side| {
+            self.get_top_levels(side, depth)
+                .iter()
+                .map(|&(_, qty)| qty as u128)
+                .sum::<u128>()
+        };
+        imbalance_of(sum(Side::Bid), sum(Side::Ask))
+    }
+
+    /// Minimum price increment, in price units
+    /// Orders must be priced on multiples of this
+    fn tick_size(&self) -> Price {
+        1
+    }
+
+    /// Minimum quantity increment
+    /// Orders must be sized in multiples of this
+    fn lot_size(&self) -> Quantity {
+        1
+    }
+}
+
+// Size-weighted touch price; the plain midpoint if neither best has size
+// (a stale sticky best reads as quantity 0)
+pub(crate) fn microprice_of(
+    bid: Price,
+    bid_qty: Quantity,
+    ask: Price,
+    ask_qty: Quantity,
+) -> Option<f64> {
+    let (bid_qty, ask_qty) = (bid_qty as f64, ask_qty as f64);
+    if bid_qty + ask_qty == 0.0 {
+        return Some((bid + ask) as f64 / 2.0);
+    }
+    Some((ask_qty * bid as f64 + bid_qty * ask as f64) / (bid_qty + ask_qty))
+}
+
+// (bid - ask) / (bid + ask), None if both are 0
+pub(crate) fn imbalance_of(bid: u128, ask: u128) -> Option<f64> {
+    if bid == 0 && ask == 0 {
+        return None;
+    }
+    let (bid, ask) = (bid as f64, ask as f64);
+    Some((bid - ask) / (bid + ask))
 }
+
+// Take up to `quantity` from levels given best first, returning (filled,
+// notional); None if there are no levels at all
+pub(crate) fn fill_from_levels(
+    levels: impl IntoIterator<Item = (Price, Quantity)>,
+    quantity: Quantity,
+) -> Option<(Quantity, u64)> {
+    let mut levels = levels.into_iter().peekable();
+    levels.peek()?;
+    let mut filled: Quantity = 0;
+    let mut notional: u64 = 0;
+    for (price, qty) in levels {
+        if filled == quantity {
+            break;
+        }
+        let take = qty.min(quantity - filled);
+        filled += take;
+        notional = notional.saturating_add((price as u64).saturating_mul(take));
+    }
+    Some((filled, notional))
+}
+
+/// Errors reported by fallible order book operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderBookError {
+    /// A sequenced update skipped past the next expected sequence number
+    SequenceGap { expected: u64, received: u64 },
+
+    /// A sequence number is older than the retained change history
+    SequenceOutOfWindow { requested: u64, oldest: u64 },
+
+    /// An order id is already resting in the book
+    DuplicateOrder(u64),
+
+    /// An order id is not resting in the book
+    UnknownOrder(u64),
+
+    /// A snapshot's levels don't match the checksum sent with it
+    ChecksumMismatch { expected: u32, actual: u32 },
+
+    /// A price outside the accepted inclusive range [low, high]
+    PriceOutOfRange {
+        price: Price,
+        low: Price,
+        high: Price,
+    },
+
+    /// A quantity that would push a side's total, or a level, past `Quantity::MAX`
+    QuantityOverflow { quantity: Quantity, side: Side },
+}
+
+impl std::fmt::Display for OrderBookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderBookError::SequenceGap { expected, received } => {
+                write!(f, "sequence gap: expected {expected}, received {received}")
+            }
+            OrderBookError::SequenceOutOfWindow { requested, oldest } => {
+                write!(
+                    f,
+                    "sequence {requested} predates retained history (oldest {oldest})"
+                )
+            }
+            OrderBookError::DuplicateOrder(id) => write!(f, "order {id} already exists"),
+            OrderBookError::UnknownOrder(id) => write!(f, "order {id} not found"),
+            OrderBookError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
+                )
+            }
+            OrderBookError::PriceOutOfRange { price, low, high } => {
+                write!(f, "price {price} outside [{low}, {high}]")
+            }
+            OrderBookError::QuantityOverflow { quantity, side } => {
+                write!(f, "quantity {quantity} overflows the {side:?} total")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderBookError {}
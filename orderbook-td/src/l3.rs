@@ -0,0 +1,162 @@
+use crate::interfaces::{OrderBook, OrderBookError, Price, Quantity, Side, Update};
+use crate::orderbook::OrderBookImpl;
+use std::collections::HashMap;
+
+// ============================================================================
+// L3 BOOK – INDIVIDUAL ORDERS OVER THE L2 AGGREGATE
+// ============================================================================
+
+pub type OrderId = u64;
+
+/// Tracks individual orders and keeps the wrapped L2 book equal to their
+/// per-price aggregate. All L2 queries go through `book()`
+pub struct L3Book {
+    book: OrderBookImpl,
+    orders: HashMap<OrderId, (Price, Quantity, Side)>,
+}
+
+impl L3Book {
+    pub fn new() -> Self {
+        L3Book {
+            book: OrderBookImpl::new(),
+            orders: HashMap::new(),
+        }
+    }
+
+    /// The aggregated L2 view
+    #[inline(always)]
+    pub fn book(&self) -> &OrderBookImpl {
+        &self.book
+    }
+
+    pub fn order(&self, id: OrderId) -> Option<(Price, Quantity, Side)> {
+        self.orders.get(&id).copied()
+    }
+
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Add a resting order; a zero quantity is accepted and ignored
+    /// Fails without changing the book if the price is outside the L2 book's
+    /// range or the order would push its level past `Quantity::MAX`
+    pub fn add_order(
+        &mut self,
+        id: OrderId,
+        price: Price,
+        quantity: Quantity,
+        side: Side,
+    ) -> Result<(), OrderBookError> {
+        if self.orders.contains_key(&id) {
+            return Err(OrderBookError::DuplicateOrder(id));
+        }
+        let high = self.book.max_price() - 1;
+        if !(0..=high).contains(&price) {
+            return Err(OrderBookError::PriceOutOfRange {
+                price,
+                low: 0,
+                high,
+            });
+        }
+        if quantity == 0 {
+            return Ok(());
+        }
+        // An order priced through the opposite touch clears the levels it
+        // crosses from the L2 book; drop the orders resting there with them
+        let crosses = match side {
+            Side::Bid => self.book.get_best_ask().is_some_and(|ask| price > ask),
+            Side::Ask => self.book.get_best_bid().is_some_and(|bid| price < bid),
+        };
+        self.adjust_level(price, side, 0, quantity)?;
+        if crosses {
+            self.orders
+                .retain(|_, &mut (resting, _, resting_side)| match resting_side {
+                    Side::Bid if side == Side::Ask => resting <= price,
+                    Side::Ask if side == Side::Bid => resting >= price,
+                    _ => true,
+                });
+        }
+        self.orders.insert(id, (price, quantity, side));
+        Ok(())
+    }
+
+    /// Remove an order, dropping the L2 level if it was the last one there
+    pub fn cancel_order(&mut self, id: OrderId) -> Result<(), OrderBookError> {
+        let (price, quantity, side) = self.order(id).ok_or(OrderBookError::UnknownOrder(id))?;
+        self.adjust_level(price, side, quantity, 0)?;
+        self.orders.remove(&id);
+        Ok(())
+    }
+
+    /// Change an order's size in place; a new quantity of 0 cancels it
+    pub fn modify_order(
+        &mut self,
+        id: OrderId,
+        new_quantity: Quantity,
+    ) -> Result<(), OrderBookError> {
+        if new_quantity == 0 {
+            return self.cancel_order(id);
+        }
+        let (price, old_quantity, side) = self.order(id).ok_or(OrderBookError::UnknownOrder(id))?;
+        self.adjust_level(price, side, old_quantity, new_quantity)?;
+        self.orders.insert(id, (price, new_quantity, side));
+        Ok(())
+    }
+
+    /// First price (walking from best) at which the running number of orders
+    /// reaches `pct` of the side's order count: the order-count analogue of
+    /// `price_at_depth_percentile`, showing where most individual orders sit
+    /// rather than most size. `pct` is clamped to [0, 1]; None if the side is empty
+    pub fn price_at_count_percentile(&self, side: Side, pct: f64) -> Option<Price> {
+        let mut counts: HashMap<Price, usize> = HashMap::new();
+        for &(price, _, order_side) in self.orders.values() {
+            if order_side == side {
+                *counts.entry(price).or_insert(0) += 1;
+            }
+        }
+        let total: usize = counts.values().sum();
+        let target = pct.clamp(0.0, 1.0) * total as f64;
+
+        let mut running = 0;
+        let mut last = None;
+        for (price, _) in self.book.levels(side) {
+            running += counts.get(&price).copied().unwrap_or(0);
+            last = Some(price);
+            if running as f64 >= target {
+                break;
+            }
+        }
+        last
+    }
+
+    // Translate an order-level change into the aggregate Set for its price
+    // The level never holds less than `removed`, so only adding can fail
+    fn adjust_level(
+        &mut self,
+        price: Price,
+        side: Side,
+        removed: Quantity,
+        added: Quantity,
+    ) -> Result<(), OrderBookError> {
+        let current = self.book.get_quantity_at(price, side).unwrap_or(0);
+        let quantity = current
+            .checked_sub(removed)
+            .and_then(|rest| rest.checked_add(added))
+            .ok_or(OrderBookError::QuantityOverflow {
+                quantity: added,
+                side,
+            })?;
+        self.book.apply_update(Update::Set {
+            price,
+            quantity,
+            side,
+        });
+        Ok(())
+    }
+}
+
+impl Default for L3Book {
+    fn default() -> Self {
+        Self::new()
+    }
+}
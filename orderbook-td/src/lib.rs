@@ -0,0 +1,17 @@
+pub mod benchmarks;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod consolidated;
+pub mod deltalog;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod interfaces;
+pub mod l3;
+pub mod mirror;
+pub mod orderbook;
+pub mod overlay;
+pub mod queue;
+pub mod replay;
+pub mod snapshot;
+pub mod testing;
+pub mod workload;
@@ -1,8 +1,8 @@
-use crate::{benchmarks::OrderBookBenchmark, orderbook::OrderBookImpl};
-
-mod benchmarks;
-mod interfaces;
-mod orderbook;
+use rust_3::{
+    benchmarks::OrderBookBenchmark,
+    orderbook::OrderBookImpl,
+    workload::{LevelSpacing, WorkloadGenerator},
+};
 
 // Objective: Complete the orderbook implementation at ./orderbook.rs and run this file to see how fast it is. Faster implementation wins !
 
@@ -16,6 +16,30 @@ fn main() {
     let result = OrderBookBenchmark::run::<OrderBookImpl>("OrderBook", 100_000);
     OrderBookBenchmark::print_results(&result);
 
+    println!("\nRunning Sparse (power-law spaced) OrderBook Benchmark...\n");
+
+    let mut workload = WorkloadGenerator::new(42, 200, LevelSpacing::PowerLaw { alpha: 1.2 });
+    let result = OrderBookBenchmark::run_with_workload::<OrderBookImpl>(
+        "OrderBook (sparse)",
+        &mut workload,
+        100_000,
+    );
+    OrderBookBenchmark::print_results(&result);
+
+    println!("\nRunning Scattered Batch Benchmark (apply_batch prefetch)...\n");
+
+    let result = OrderBookBenchmark::run_scattered_batch(1_000);
+    OrderBookBenchmark::print_scattered_batch(&result);
+
+    println!("\nRunning Density Sweep...\n");
+
+    let results = OrderBookBenchmark::run_density_sweep::<OrderBookImpl>(
+        "OrderBook",
+        &[10, 100, 1_000, 10_000],
+        20_000,
+    );
+    OrderBookBenchmark::print_density_sweep(&results);
+
     println!("\n Competition Goal: Achieve sub-nanosecond operations!");
     println!(" Tips:");
     println!("   - Use cache-friendly data structures");
@@ -31,9 +55,25 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        interfaces::{OrderBook, Side, Update},
-        orderbook::OrderBookImpl,
+    use std::time::Duration;
+
+    use rust_3::{
+        consolidated::ConsolidatedView,
+        deltalog::{DeltaLogReader, DeltaLogWriter},
+        interfaces::{OrderBook, OrderBookError, Price, Quantity, Side, Update},
+        l3::L3Book,
+        mirror::SamplingMirror,
+        orderbook::{
+            BboSnapshot, BookSnapshot, ChecksumFormat, DisplayRow, ExecutionReport, HaltPolicy,
+            OrderBookImpl, TopTwo, ValidatedPrice, ValidatedUpdate, quantity_fits_f64,
+            quantity_to_f64_checked, sanitize_stream,
+        },
+        overlay::OverlayBook,
+        queue::UpdateQueue,
+        replay::replay_with_callback,
+        snapshot::{SnapshotScheduler, load_latest},
+        testing::stress_test,
+        workload::{LevelSpacing, WorkloadGenerator, XorShift64},
     };
 
     fn test_basic_operations<T: OrderBook>() {
@@ -108,9 +148,3223 @@ mod tests {
         assert_eq!(ob.get_quantity_at(10000, Side::Bid), None);
     }
 
+    // Integer books have no configured increments yet, so both default to 1
+    // (the f64 instantiation isn't an `OrderBook` and has none to report)
+    fn test_increments<T: OrderBook>() {
+        let ob = T::new();
+        assert_eq!(ob.tick_size(), 1);
+        assert_eq!(ob.lot_size(), 1);
+    }
+
+    fn test_direct_set_remove<T: OrderBook>() {
+        let mut direct = T::new();
+        let mut via_update = T::new();
+        let ops = [
+            (100, 5, Side::Bid),
+            (101, 7, Side::Bid),
+            (103, 2, Side::Ask),
+            (101, 0, Side::Bid),
+            (104, 9, Side::Ask),
+            (100, 3, Side::Bid),
+        ];
+        for (price, quantity, side) in ops {
+            direct.set(price, quantity, side);
+            via_update.apply_update(Update::Set {
+                price,
+                quantity,
+                side,
+            });
+        }
+        direct.remove(103, Side::Ask);
+        via_update.apply_update(Update::Remove {
+            price: 103,
+            side: Side::Ask,
+        });
+
+        for side in [Side::Bid, Side::Ask] {
+            assert_eq!(
+                direct.get_top_levels(side, 10),
+                via_update.get_top_levels(side, 10)
+            );
+            assert_eq!(
+                direct.get_total_quantity(side),
+                via_update.get_total_quantity(side)
+            );
+        }
+        assert_eq!(direct.get_best_bid(), Some(100));
+        assert_eq!(direct.get_best_ask(), Some(104));
+    }
+
     #[test]
     fn test_naive_implementation() {
         test_basic_operations::<OrderBookImpl>();
         test_updates_and_removes::<OrderBookImpl>();
+        test_increments::<OrderBookImpl>();
+        test_direct_set_remove::<OrderBookImpl>();
+    }
+
+    fn book_from(bids: &[(Price, Quantity)], asks: &[(Price, Quantity)]) -> OrderBookImpl {
+        let mut ob = OrderBookImpl::new();
+        for &(price, quantity) in bids {
+            ob.apply_update(Update::Set {
+                price,
+                quantity,
+                side: Side::Bid,
+            });
+        }
+        for &(price, quantity) in asks {
+            ob.apply_update(Update::Set {
+                price,
+                quantity,
+                side: Side::Ask,
+            });
+        }
+        ob
+    }
+
+    #[test]
+    fn test_depth_weighted_mid() {
+        let ob = book_from(&[(10000, 100), (9900, 300)], &[(10100, 100), (10300, 100)]);
+
+        // Bid VWAP = (10000*100 + 9900*300) / 400 = 9925
+        // Ask VWAP = (10100*100 + 10300*100) / 200 = 10200
+        assert_eq!(ob.vwap(Side::Bid, 2), Some(9925.0));
+        assert_eq!(ob.vwap(Side::Ask, 2), Some(10200.0));
+        assert_eq!(ob.depth_weighted_mid(2), Some(10062.5));
+        assert_eq!(ob.depth_weighted_mid(1), Some(10050.0));
+
+        let one_sided = book_from(&[(10000, 100)], &[]);
+        assert_eq!(one_sided.depth_weighted_mid(5), None);
+    }
+
+    #[test]
+    fn test_resync_drops_buffered_updates_covered_by_snapshot() {
+        let mut ob = OrderBookImpl::new();
+
+        // Deltas buffered from the websocket while the REST snapshot was in flight
+        let buffered = [
+            (
+                5,
+                Update::Set {
+                    price: 10000,
+                    quantity: 1,
+                    side: Side::Bid,
+                },
+            ),
+            (
+                6,
+                Update::Set {
+                    price: 10100,
+                    quantity: 2,
+                    side: Side::Ask,
+                },
+            ),
+            (
+                7,
+                Update::Remove {
+                    price: 9900,
+                    side: Side::Bid,
+                },
+            ),
+            (
+                8,
+                Update::Set {
+                    price: 10000,
+                    quantity: 150,
+                    side: Side::Bid,
+                },
+            ),
+            (
+                9,
+                Update::Set {
+                    price: 10050,
+                    quantity: 60,
+                    side: Side::Ask,
+                },
+            ),
+        ];
+
+        // Snapshot already reflects everything up to seq 7
+        ob.resync(&[(10000, 100)], &[(10100, 80)], 7);
+        assert_eq!(ob.sequence(), 7);
+
+        let applied: Vec<bool> = buffered
+            .iter()
+            .map(|(seq, update)| ob.apply_update_seq(update.clone(), *seq).unwrap())
+            .collect();
+        assert_eq!(applied, vec![false, false, false, true, true]);
+        assert_eq!(ob.sequence(), 9);
+        assert_eq!(ob.get_quantity_at(10000, Side::Bid), Some(150));
+        assert_eq!(ob.get_best_ask(), Some(10050));
+        assert_eq!(ob.get_quantity_at(10100, Side::Ask), Some(80));
+
+        // A missed message is reported and leaves the book untouched
+        let gap = ob.apply_update_seq(
+            Update::Remove {
+                price: 10000,
+                side: Side::Bid,
+            },
+            11,
+        );
+        assert_eq!(
+            gap,
+            Err(OrderBookError::SequenceGap {
+                expected: 10,
+                received: 11
+            })
+        );
+        assert_eq!(ob.get_quantity_at(10000, Side::Bid), Some(150));
+        assert_eq!(ob.sequence(), 9);
+
+        // Resyncing replaces the previous state entirely
+        ob.resync(&[(9000, 10)], &[], 20);
+        assert_eq!(ob.get_best_bid(), Some(9000));
+        assert_eq!(ob.get_best_ask(), None);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 10);
+        assert_eq!(ob.get_quantity_at(10000, Side::Bid), None);
+    }
+
+    #[test]
+    fn test_best_quantity_cache_tracks_in_place_updates() {
+        let mut ob = book_from(&[(10000, 100), (9900, 50)], &[(10100, 80), (10200, 40)]);
+        assert_eq!(ob.get_best_bid_with_qty(), Some((10000, 100)));
+        assert_eq!(ob.get_best_ask_with_qty(), Some((10100, 80)));
+
+        // Set at the current best price modifies the cached quantity in place
+        ob.apply_update(Update::Set {
+            price: 10000,
+            quantity: 175,
+            side: Side::Bid,
+        });
+        ob.apply_update(Update::Set {
+            price: 10100,
+            quantity: 5,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.get_best_bid_with_qty(), Some((10000, 175)));
+        assert_eq!(ob.get_best_ask_with_qty(), Some((10100, 5)));
+
+        // Updates behind the touch leave the cache alone
+        ob.apply_update(Update::Set {
+            price: 9900,
+            quantity: 999,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.get_best_bid_with_qty(), Some((10000, 175)));
+
+        // Removing the best picks up the next level's quantity
+        ob.apply_update(Update::Remove {
+            price: 10000,
+            side: Side::Bid,
+        });
+        ob.apply_update(Update::Set {
+            price: 10100,
+            quantity: 0,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.get_best_bid_with_qty(), Some((9900, 999)));
+        assert_eq!(ob.get_best_ask_with_qty(), Some((10200, 40)));
+
+        // A new better level replaces the cache
+        ob.apply_update(Update::Set {
+            price: 10150,
+            quantity: 7,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.get_best_ask_with_qty(), Some((10150, 7)));
+
+        ob.clear();
+        assert_eq!(ob.get_best_bid_with_qty(), None);
+        assert_eq!(ob.get_best_ask_with_qty(), None);
+    }
+
+    #[test]
+    fn test_changed_prices_since_includes_uncrossed_levels() {
+        let mut ob: OrderBookImpl = OrderBookImpl::new();
+        let set = |price, side| Update::Set {
+            price,
+            quantity: 5,
+            side,
+        };
+        ob.apply_update_seq(set(105, Side::Ask), 1).unwrap();
+        ob.apply_update_seq(set(107, Side::Ask), 2).unwrap();
+        ob.apply_update_seq(set(120, Side::Ask), 3).unwrap();
+        ob.apply_update_seq(set(110, Side::Bid), 4).unwrap();
+        assert_eq!(ob.get_best_ask(), Some(120));
+        assert_eq!(
+            ob.changed_prices_since(3),
+            Ok(vec![(110, Side::Bid), (105, Side::Ask), (107, Side::Ask)])
+        );
+
+        // And from the ask side
+        ob.apply_update_seq(set(100, Side::Ask), 5).unwrap();
+        assert_eq!(
+            ob.changed_prices_since(4),
+            Ok(vec![(100, Side::Ask), (110, Side::Bid)])
+        );
+
+        // Replaying the listed levels brings a delta consumer in line
+        let mut mirror: OrderBookImpl = OrderBookImpl::new();
+        mirror.resync(&[], &[(105, 5), (107, 5), (120, 5)], 3);
+        for (price, side) in ob.changed_prices_since(3).unwrap() {
+            mirror.set(price, ob.get_quantity_at(price, side).unwrap_or(0), side);
+        }
+        assert_eq!(mirror.fingerprint(), ob.fingerprint());
+    }
+
+    #[test]
+    fn test_changed_prices_since() {
+        let mut ob = OrderBookImpl::new();
+        ob.resync(&[(10000, 100)], &[(10100, 100)], 10);
+
+        let updates = [
+            Update::Set {
+                price: 9990,
+                quantity: 5,
+                side: Side::Bid,
+            },
+            Update::Set {
+                price: 10100,
+                quantity: 50,
+                side: Side::Ask,
+            },
+            Update::Remove {
+                price: 9990,
+                side: Side::Bid,
+            },
+        ];
+        for (i, update) in updates.into_iter().enumerate() {
+            ob.apply_update_seq(update, 11 + i as u64).unwrap();
+        }
+
+        assert_eq!(
+            ob.changed_prices_since(10),
+            Ok(vec![(9990, Side::Bid), (10100, Side::Ask)])
+        );
+        assert_eq!(
+            ob.changed_prices_since(11),
+            Ok(vec![(10100, Side::Ask), (9990, Side::Bid)])
+        );
+        assert_eq!(ob.changed_prices_since(13), Ok(vec![]));
+
+        // History before the snapshot is unknown
+        assert_eq!(
+            ob.changed_prices_since(9),
+            Err(OrderBookError::SequenceOutOfWindow {
+                requested: 9,
+                oldest: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_log_depth_samples() {
+        let ob = book_from(
+            &[(10000, 10), (9999, 20), (9997, 30), (9990, 40)],
+            &[(10010, 5), (10013, 7)],
+        );
+
+        assert_eq!(
+            ob.log_depth_samples(Side::Bid, 4),
+            vec![(9999, 30), (9998, 30), (9996, 60), (9992, 60)]
+        );
+        assert_eq!(
+            ob.log_depth_samples(Side::Ask, 3),
+            vec![(10011, 5), (10012, 5), (10014, 12)]
+        );
+        assert!(
+            OrderBookImpl::new()
+                .log_depth_samples(Side::Bid, 4)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_validated_price() {
+        let mut ob = OrderBookImpl::new();
+        assert!(ValidatedPrice::new(-5, &ob).is_none());
+        assert!(ValidatedPrice::new(ob.max_price(), &ob).is_none());
+
+        let price = ValidatedPrice::new(ob.max_price() - 1, &ob).unwrap();
+        ob.apply_validated(ValidatedUpdate::Set {
+            price,
+            quantity: 10,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.get_best_ask(), Some(ob.max_price() - 1));
+
+        ob.apply_validated(ValidatedUpdate::Remove {
+            price,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.get_best_ask(), None);
+    }
+
+    #[test]
+    fn test_quantity_in_band() {
+        let ob = book_from(
+            &[(63, 1), (64, 2), (127, 4), (128, 8), (10000, 16)],
+            &[(20000, 5)],
+        );
+
+        assert_eq!(ob.quantity_in_band(Side::Bid, 0, 200_000), 31);
+        assert_eq!(ob.quantity_in_band(Side::Bid, 63, 128), 15);
+        assert_eq!(ob.quantity_in_band(Side::Bid, 64, 127), 6);
+        assert_eq!(ob.quantity_in_band(Side::Bid, 65, 126), 0);
+        assert_eq!(ob.quantity_in_band(Side::Bid, 128, 128), 8);
+        assert_eq!(ob.quantity_in_band(Side::Bid, -100, 63), 1);
+        assert_eq!(ob.quantity_in_band(Side::Bid, 500, 100), 0);
+        assert_eq!(ob.quantity_in_band(Side::Bid, 300_000, 400_000), 0);
+        assert_eq!(ob.quantity_in_band(Side::Ask, 19_999, i64::MAX), 5);
+    }
+
+    #[test]
+    fn test_per_level_imbalance() {
+        let ob = book_from(
+            &[(10000, 30), (9000, 50)],
+            &[(10100, 10), (10101, 50), (10102, 20)],
+        );
+
+        assert_eq!(ob.per_level_imbalance(4), vec![0.5, 0.0, -1.0, 0.0]);
+        assert!(ob.per_level_imbalance(0).is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let a = book_from(&[(10000, 100), (9900, 50)], &[(10100, 80)]);
+        let mut b = book_from(&[(9900, 50)], &[(10100, 80), (10200, 1)]);
+        assert_ne!(a.fingerprint(), b.fingerprint());
+
+        // Same levels reached through a different history
+        b.apply_update(Update::Remove {
+            price: 10200,
+            side: Side::Ask,
+        });
+        b.apply_update(Update::Set {
+            price: 10000,
+            quantity: 100,
+            side: Side::Bid,
+        });
+        assert_eq!(a.fingerprint(), b.fingerprint());
+
+        // A single quantity change or side swap is detected
+        b.apply_update(Update::Set {
+            price: 9900,
+            quantity: 51,
+            side: Side::Bid,
+        });
+        assert_ne!(a.fingerprint(), b.fingerprint());
+        let swapped = book_from(&[(10100, 80)], &[(10000, 100), (9900, 50)]);
+        assert_ne!(a.fingerprint(), swapped.fingerprint());
+    }
+
+    #[test]
+    fn test_get_top_two() {
+        let ob = book_from(&[(10000, 10), (9000, 20), (8000, 30)], &[(10100, 5)]);
+        let top = ob.get_top_two();
+        assert_eq!(top.bid0, Some((10000, 10)));
+        assert_eq!(top.bid1, Some((9000, 20)));
+        assert_eq!(top.ask0, Some((10100, 5)));
+        assert_eq!(top.ask1, None);
+
+        assert_eq!(OrderBookImpl::new().get_top_two(), TopTwo::default());
+    }
+
+    #[test]
+    fn test_sampling_mirror_throttles_only_emission() {
+        let mut emitted = Vec::new();
+        let mut mirror =
+            SamplingMirror::new(OrderBookImpl::new(), 3, None, |ob: &OrderBookImpl| {
+                emitted.push(ob.get_best_bid())
+            });
+
+        let mut sent = 0;
+        for price in 1..=7 {
+            if mirror.apply_update(Update::Set {
+                price: 10000 + price,
+                quantity: 1,
+                side: Side::Bid,
+            }) {
+                sent += 1;
+            }
+        }
+
+        // Inner book saw all seven updates
+        assert_eq!(mirror.book().get_best_bid(), Some(10007));
+        assert_eq!(mirror.book().get_total_quantity(Side::Bid), 7);
+        assert_eq!(sent, 2);
+
+        mirror.flush();
+        drop(mirror);
+        assert_eq!(emitted, vec![Some(10003), Some(10006), Some(10007)]);
+    }
+
+    #[test]
+    fn test_sampling_mirror_time_trigger() {
+        let mut count = 0;
+        let mut mirror = SamplingMirror::new(
+            OrderBookImpl::new(),
+            0,
+            Some(Duration::ZERO),
+            |_: &OrderBookImpl| count += 1,
+        );
+        assert!(mirror.apply_update(Update::Remove {
+            price: 10000,
+            side: Side::Ask,
+        }));
+        drop(mirror);
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_all_levels() {
+        let mut ob = book_from(
+            &[(10000, 1), (5, 2), (150000, 3)],
+            &[(150001, 4), (199999, 5), (160000, 6)],
+        );
+        ob.apply_update(Update::Set {
+            price: 5,
+            quantity: 0,
+            side: Side::Bid,
+        });
+        ob.apply_update(Update::Set {
+            price: 10000,
+            quantity: 10,
+            side: Side::Bid,
+        });
+
+        assert_eq!(ob.level_count(Side::Bid), 2);
+        assert_eq!(ob.all_levels(Side::Bid), vec![(150000, 3), (10000, 10)]);
+        assert_eq!(
+            ob.all_levels(Side::Ask),
+            vec![(150001, 4), (160000, 6), (199999, 5)]
+        );
+        assert_eq!(ob.all_levels(Side::Ask).capacity(), 3);
+        assert!(OrderBookImpl::new().all_levels(Side::Bid).is_empty());
+    }
+
+    #[test]
+    fn test_cross_amount() {
+        assert_eq!(book_from(&[(10000, 1)], &[(10050, 1)]).cross_amount(), None);
+        assert_eq!(book_from(&[(10000, 1)], &[(10000, 1)]).cross_amount(), None);
+        // The later ask clears the bid it crosses instead of crossing the book
+        assert_eq!(book_from(&[(10030, 1)], &[(10000, 1)]).cross_amount(), None);
+        assert_eq!(book_from(&[(10030, 1)], &[]).cross_amount(), None);
+    }
+
+    #[test]
+    fn test_touch_flicker_count() {
+        let mut ob = book_from(&[(10000, 10), (9990, 10)], &[(10010, 10)]);
+        let flicker = |ob: &mut OrderBookImpl| {
+            ob.apply_update(Update::Remove {
+                price: 10000,
+                side: Side::Bid,
+            });
+            ob.apply_update(Update::Set {
+                price: 10000,
+                quantity: 10,
+                side: Side::Bid,
+            });
+        };
+
+        // Off by default
+        flicker(&mut ob);
+        assert_eq!(ob.touch_flicker_count(), 0);
+
+        ob.set_flicker_tracking(true);
+        flicker(&mut ob);
+        flicker(&mut ob);
+
+        // Removals behind the touch don't count
+        ob.apply_update(Update::Remove {
+            price: 9990,
+            side: Side::Bid,
+        });
+        ob.apply_update(Update::Set {
+            price: 10010,
+            quantity: 0,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.touch_flicker_count(), 3);
+
+        ob.reset_flicker_count();
+        assert_eq!(ob.touch_flicker_count(), 0);
+    }
+
+    #[test]
+    fn test_truncate_depth() {
+        let mut ob = book_from(
+            &[(10000, 1), (9999, 2), (9000, 3), (10, 4)],
+            &[(10001, 5), (20000, 6)],
+        );
+        ob.truncate_depth(2);
+
+        assert_eq!(ob.all_levels(Side::Bid), vec![(10000, 1), (9999, 2)]);
+        assert_eq!(ob.all_levels(Side::Ask), vec![(10001, 5), (20000, 6)]);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 3);
+        assert_eq!(ob.level_count(Side::Bid), 2);
+        assert_eq!(ob.get_best_bid(), Some(10000));
+
+        ob.truncate_depth(0);
+        assert_eq!(ob.get_best_bid(), None);
+        assert_eq!(ob.get_best_ask(), None);
+        assert_eq!(ob.get_total_quantity(Side::Ask), 0);
+    }
+
+    #[test]
+    fn test_would_change_bbo_matches_apply() {
+        // Plain, then with sticky bests deferring rescans
+        for sticky in [0, 1, 3] {
+            check_would_change_bbo(sticky);
+        }
+    }
+
+    fn check_would_change_bbo(sticky: u32) {
+        let mut rng = XorShift64::new(7);
+        let mut ob = OrderBookImpl::new();
+        ob.set_sticky_best(sticky);
+
+        for _ in 0..20_000 {
+            // Halted stretches: updates are held, so nothing may change
+            if rng.range(0, 50) == 0 {
+                if ob.is_halted() {
+                    ob.resume();
+                } else {
+                    ob.halt();
+                }
+            }
+            // Now and then a price the book ignores
+            let price = match rng.range(0, 100) {
+                0 => -5,
+                1 => ob.max_price() + 800_000,
+                _ => rng.range(10000, 10040) as Price,
+            };
+            let side = if rng.next_u64() & 1 == 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let update = match rng.range(0, 9) {
+                0..=1 => Update::Remove { price, side },
+                2 => Update::Set {
+                    price,
+                    quantity: 0,
+                    side,
+                },
+                _ => Update::Set {
+                    price,
+                    quantity: rng.range(1, 3),
+                    side,
+                },
+            };
+
+            let before = (ob.get_best_bid_with_qty(), ob.get_best_ask_with_qty());
+            let predicted = ob.would_change_bbo(&update);
+            ob.apply_update(update.clone());
+            let after = (ob.get_best_bid_with_qty(), ob.get_best_ask_with_qty());
+            assert_eq!(predicted, before != after, "sticky {sticky}: {update:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_marketable() {
+        let ob = book_from(&[(10000, 1)], &[(10010, 1)]);
+        assert!(ob.is_marketable(10010, Side::Bid));
+        assert!(ob.is_marketable(10020, Side::Bid));
+        assert!(!ob.is_marketable(10009, Side::Bid));
+        assert!(ob.is_marketable(10000, Side::Ask));
+        assert!(!ob.is_marketable(10001, Side::Ask));
+
+        let bids_only = book_from(&[(10000, 1)], &[]);
+        assert!(!bids_only.is_marketable(99999, Side::Bid));
+        assert!(bids_only.is_marketable(9000, Side::Ask));
+    }
+
+    #[test]
+    fn test_banded_sums_track_in_place_updates() {
+        let mut rng = XorShift64::new(11);
+        let mut ob = OrderBookImpl::new();
+        let mut shadow = std::collections::BTreeMap::new();
+
+        for _ in 0..20_000 {
+            let price = rng.range(9800, 10200) as Price;
+            let quantity = if rng.range(0, 3) == 0 {
+                0
+            } else {
+                rng.range(1, 500)
+            };
+            ob.apply_update(Update::Set {
+                price,
+                quantity,
+                side: Side::Bid,
+            });
+            if quantity == 0 {
+                shadow.remove(&price);
+            } else {
+                shadow.insert(price, quantity);
+            }
+        }
+
+        for (low, high) in [(0, 200_000), (9800, 10200), (9857, 10111), (10000, 10000)] {
+            let expected: Quantity = shadow.range(low..=high).map(|(_, q)| q).sum();
+            assert_eq!(ob.quantity_in_band(Side::Bid, low, high), expected);
+        }
+
+        let best = ob.get_best_bid().unwrap();
+        let expected: Quantity = shadow.range(9990..).map(|(_, q)| q).sum();
+        assert_eq!(ob.get_depth_until(Side::Bid, 9990), expected);
+        assert_eq!(ob.get_depth_until(Side::Bid, best), shadow[&best]);
+        assert_eq!(ob.get_depth_until(Side::Ask, 10000), 0);
+    }
+
+    #[test]
+    fn test_is_one_sided() {
+        assert_eq!(OrderBookImpl::new().is_one_sided(), None);
+        assert_eq!(book_from(&[(10000, 1)], &[(10010, 1)]).is_one_sided(), None);
+        assert_eq!(
+            book_from(&[(10000, 1)], &[]).is_one_sided(),
+            Some(Side::Bid)
+        );
+        assert_eq!(
+            book_from(&[], &[(10010, 1)]).is_one_sided(),
+            Some(Side::Ask)
+        );
+    }
+
+    #[test]
+    fn test_apply_update_ordered_reorders_within_window() {
+        let mut ob = OrderBookImpl::new();
+        ob.resync(&[], &[], 100);
+        let set = |price| Update::Set {
+            price,
+            quantity: 1,
+            side: Side::Bid,
+        };
+
+        // 103 and 102 arrive before 101
+        assert_eq!(ob.apply_update_ordered(set(10003), 103), Ok(vec![]));
+        assert_eq!(ob.apply_update_ordered(set(10002), 102), Ok(vec![]));
+        assert_eq!(ob.reorder_pending(), 2);
+        assert_eq!(ob.get_best_bid(), None);
+
+        assert_eq!(ob.apply_update_ordered(set(10001), 101), Ok(vec![]));
+        assert_eq!(ob.sequence(), 103);
+        assert_eq!(ob.reorder_pending(), 0);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 3);
+
+        // Duplicates are reported as dropped
+        assert_eq!(ob.apply_update_ordered(set(10002), 102), Ok(vec![102]));
+
+        // Too far ahead means the feed lost data
+        assert_eq!(
+            ob.apply_update_ordered(set(10500), 500),
+            Err(OrderBookError::SequenceGap {
+                expected: 104,
+                received: 500
+            })
+        );
+
+        // Buffered updates overtaken by a resync are discarded
+        assert_eq!(ob.apply_update_ordered(set(10005), 105), Ok(vec![]));
+        ob.resync(&[(9000, 1)], &[], 110);
+        assert_eq!(ob.apply_update_ordered(set(10011), 111), Ok(vec![105]));
+        assert_eq!(ob.get_best_bid(), Some(10011));
+    }
+
+    #[test]
+    fn test_delta_log_round_trip() {
+        let mut rng = XorShift64::new(3);
+        let mut updates = vec![
+            Update::Set {
+                price: i64::MAX,
+                quantity: u64::MAX,
+                side: Side::Ask,
+            },
+            Update::Remove {
+                price: i64::MIN,
+                side: Side::Bid,
+            },
+            Update::Set {
+                price: 0,
+                quantity: 0,
+                side: Side::Bid,
+            },
+        ];
+        for _ in 0..10_000 {
+            let price = 100_000 + rng.range(0, 200) as Price - 100;
+            let side = if rng.next_u64() & 1 == 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            updates.push(if rng.range(0, 4) == 0 {
+                Update::Remove { price, side }
+            } else {
+                Update::Set {
+                    price,
+                    quantity: rng.range(0, 1 << 20),
+                    side,
+                }
+            });
+        }
+
+        let mut writer = DeltaLogWriter::new(Vec::new());
+        for update in &updates {
+            writer.write(update).unwrap();
+        }
+        let bytes = writer.into_inner();
+        assert!(bytes.len() < updates.len() * 6);
+
+        let decoded: Vec<Update> = DeltaLogReader::new(bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, updates);
+
+        // A record cut short is an error, not a silent end of stream
+        let mut reader = DeltaLogReader::new(&bytes[..bytes.len() - 1]);
+        assert!(reader.by_ref().any(|r| r.is_err()));
+
+        // Bid Set at price 0 whose quantity is a ten-byte varint: u64::MAX
+        // fits, but bits past 63 or an 11th byte are corrupt
+        let record = |last: &[u8]| {
+            let mut bytes = vec![0, 0];
+            bytes.extend([0xff; 9]);
+            bytes.extend(last);
+            DeltaLogReader::new(bytes.as_slice()).next().unwrap()
+        };
+        assert_eq!(
+            record(&[0x01]).unwrap(),
+            Update::Set {
+                price: 0,
+                quantity: u64::MAX,
+                side: Side::Bid
+            }
+        );
+        for last in [&[0x02][..], &[0x7f], &[0x81, 0x00]] {
+            let err = record(last).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_price_at_depth_percentile() {
+        let ob = book_from(&[(10000, 10), (9990, 40), (9900, 50)], &[]);
+
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 0.0), Some(10000));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 0.1), Some(10000));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 0.11), Some(9990));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 0.5), Some(9990));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 0.9), Some(9900));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 7.0), Some(9900));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, -1.0), Some(10000));
+        assert_eq!(ob.price_at_depth_percentile(Side::Ask, 0.5), None);
+    }
+
+    #[test]
+    fn test_l3_book_maintains_aggregates() {
+        let mut l3 = L3Book::new();
+        l3.add_order(1, 10000, 100, Side::Bid).unwrap();
+        l3.add_order(2, 10000, 50, Side::Bid).unwrap();
+        l3.add_order(3, 9990, 70, Side::Bid).unwrap();
+        l3.add_order(4, 10010, 30, Side::Ask).unwrap();
+        assert_eq!(
+            l3.add_order(1, 1, 1, Side::Ask),
+            Err(OrderBookError::DuplicateOrder(1))
+        );
+
+        assert_eq!(l3.book().get_quantity_at(10000, Side::Bid), Some(150));
+        assert_eq!(l3.book().get_total_quantity(Side::Bid), 220);
+
+        l3.modify_order(2, 20).unwrap();
+        assert_eq!(l3.book().get_quantity_at(10000, Side::Bid), Some(120));
+        assert_eq!(l3.order(2), Some((10000, 20, Side::Bid)));
+
+        // Cancelling one of two orders keeps the level
+        l3.cancel_order(1).unwrap();
+        assert_eq!(l3.book().get_best_bid_with_qty(), Some((10000, 20)));
+
+        // Cancelling the last order at a price removes the level
+        l3.modify_order(2, 0).unwrap();
+        assert_eq!(l3.book().get_quantity_at(10000, Side::Bid), None);
+        assert_eq!(l3.book().get_best_bid(), Some(9990));
+
+        assert_eq!(l3.cancel_order(2), Err(OrderBookError::UnknownOrder(2)));
+        assert_eq!(l3.modify_order(9, 5), Err(OrderBookError::UnknownOrder(9)));
+        assert_eq!(l3.order_count(), 2);
+
+        // Size sits at the touch, orders further out
+        let mut l3 = L3Book::new();
+        l3.add_order(1, 10000, 1000, Side::Bid).unwrap();
+        for id in 2..6 {
+            l3.add_order(id, 9990, 10, Side::Bid).unwrap();
+        }
+        assert_eq!(
+            l3.book().price_at_depth_percentile(Side::Bid, 0.5),
+            Some(10000)
+        );
+        assert_eq!(l3.price_at_count_percentile(Side::Bid, 0.2), Some(10000));
+        assert_eq!(l3.price_at_count_percentile(Side::Bid, 0.5), Some(9990));
+        assert_eq!(l3.price_at_count_percentile(Side::Ask, 0.5), None);
+    }
+
+    #[test]
+    fn test_stress_invariants() {
+        for seed in 0..4 {
+            stress_test(seed, 300).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_side_vwap_tracks_notional() {
+        let mut ob = book_from(&[(100, 10), (90, 30)], &[]);
+        assert_eq!(ob.total_notional(Side::Bid), 3700);
+        assert_eq!(ob.side_vwap(Side::Bid), Some(92.5));
+
+        // In-place quantity change
+        ob.apply_update(Update::Set {
+            price: 100,
+            quantity: 30,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.total_notional(Side::Bid), 5700);
+        assert_eq!(ob.side_vwap(Side::Bid), Some(95.0));
+
+        // Zero-Set and Remove
+        ob.apply_update(Update::Set {
+            price: 90,
+            quantity: 0,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.side_vwap(Side::Bid), Some(100.0));
+        ob.apply_update(Update::Remove {
+            price: 100,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.total_notional(Side::Bid), 0);
+        assert_eq!(ob.side_vwap(Side::Bid), None);
+        assert_eq!(ob.side_vwap(Side::Ask), None);
+        ob.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_best_distance_from_reference() {
+        let mut ob = book_from(&[(9990, 1)], &[(10020, 1)]);
+        assert_eq!(ob.best_distance_from_reference(), None);
+
+        ob.set_reference_price(Some(10000));
+        assert_eq!(ob.best_distance_from_reference(), Some((10, 20)));
+
+        ob.set_reference_price(Some(10030));
+        assert_eq!(ob.best_distance_from_reference(), Some((40, -10)));
+
+        ob.apply_update(Update::Remove {
+            price: 10020,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.best_distance_from_reference(), None);
+    }
+
+    #[test]
+    fn test_top_levels_into_matches_get_top_levels() {
+        let mut workload =
+            WorkloadGenerator::new(5, 50, LevelSpacing::Geometric { mean_gap: 20.0 });
+        let mut ob = OrderBookImpl::new();
+        workload.populate(&mut ob);
+
+        let mut buffer = Vec::with_capacity(64);
+        for side in [Side::Bid, Side::Ask] {
+            for n in [0, 1, 10, 50, 80] {
+                ob.top_levels_into(side, n, &mut buffer);
+                assert_eq!(buffer, ob.get_top_levels(side, n));
+            }
+        }
+        assert_eq!(buffer.capacity(), 64);
+    }
+
+    #[test]
+    fn test_deep_microprice() {
+        // Thin touch on the bid, deep size behind it
+        let ob = book_from(&[(100, 1), (99, 99)], &[(102, 50)]);
+
+        // Touch only: (50 * 100 + 1 * 102) / 51
+        let touch = ob.deep_microprice(1).unwrap();
+        assert!((touch - 5102.0 / 51.0).abs() < 1e-9);
+
+        // Two levels: bid VWAP 99.01 on 100 lots vs ask 102 on 50 lots
+        let deep = ob.deep_microprice(2).unwrap();
+        assert!((deep - (50.0 * 99.01 + 100.0 * 102.0) / 150.0).abs() < 1e-9);
+        assert!(deep > touch);
+
+        assert_eq!(book_from(&[(100, 1)], &[]).deep_microprice(3), None);
+    }
+
+    #[test]
+    fn test_halt_buffers_and_rejects() {
+        let mut ob = book_from(&[(100, 10)], &[(105, 10)]);
+        assert!(!ob.is_halted());
+        assert_eq!(ob.halt_policy(), HaltPolicy::Buffer);
+
+        ob.halt();
+        assert!(ob.is_halted());
+        ob.apply_update(Update::Set {
+            price: 101,
+            quantity: 5,
+            side: Side::Bid,
+        });
+        ob.apply_update(Update::Remove {
+            price: 101,
+            side: Side::Bid,
+        });
+        ob.apply_update(Update::Set {
+            price: 102,
+            quantity: 7,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.get_best_bid(), Some(100));
+        assert_eq!(ob.halt_pending(), 3);
+
+        assert_eq!(ob.resume(), 3);
+        assert!(!ob.is_halted());
+        assert_eq!(ob.get_best_bid(), Some(102));
+        assert_eq!(ob.get_quantity_at(101, Side::Bid), None);
+        assert_eq!(ob.halt_pending(), 0);
+
+        ob.set_halt_policy(HaltPolicy::Reject);
+        ob.halt();
+        ob.apply_update(Update::Remove {
+            price: 105,
+            side: Side::Ask,
+        });
+        assert_eq!(ob.halt_pending(), 0);
+        assert_eq!(ob.resume(), 0);
+        assert_eq!(ob.get_best_ask(), Some(105));
+        assert!(ob.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_snapshot_load_discards_held_updates() {
+        let mut ob = book_from(&[(100, 1)], &[]);
+        ob.halt();
+        ob.set(90, 7, Side::Bid);
+        ob.resync(&[(100, 5)], &[(110, 3)], 10);
+        assert_eq!(ob.halt_pending(), 0);
+        assert_eq!(ob.resume(), 0);
+        assert_eq!(ob.get_quantity_at(90, Side::Bid), None);
+        assert_eq!(ob.all_levels(Side::Bid), vec![(100, 5)]);
+
+        // A rejected snapshot keeps them, a verified one drops them
+        ob.halt();
+        ob.set(90, 7, Side::Bid);
+        assert!(ob.load_verified_snapshot(&[(99, 1)], &[], 0, 10).is_err());
+        assert_eq!(ob.halt_pending(), 1);
+        let good = book_from(&[(99, 1)], &[]).checksum(10);
+        ob.load_verified_snapshot(&[(99, 1)], &[], good, 10)
+            .unwrap();
+        assert_eq!(ob.halt_pending(), 0);
+
+        // Replayed updates go through the live path, recent tracking included
+        ob.set_recent_tracking(4);
+        ob.set(95, 2, Side::Bid);
+        assert_eq!(ob.resume(), 1);
+        assert_eq!(ob.recent_levels(), &[(95, Side::Bid)]);
+        assert_eq!(ob.get_best_bid(), Some(99));
+    }
+
+    #[test]
+    fn test_near_touch() {
+        let ob = book_from(
+            &[(100, 10), (99, 5), (97, 3), (40, 1)],
+            &[(127, 4), (128, 6), (131, 2)],
+        );
+
+        let (bids, asks) = ob.near_touch(0);
+        assert_eq!(bids, vec![(100, 10)]);
+        assert_eq!(asks, vec![(127, 4)]);
+
+        // Window straddles the 64/128 block boundaries
+        let (bids, asks) = ob.near_touch(3);
+        assert_eq!(bids, vec![(100, 10), (99, 5), (97, 3)]);
+        assert_eq!(asks, vec![(127, 4), (128, 6)]);
+
+        let (bids, asks) = ob.near_touch(1000);
+        assert_eq!(bids, ob.all_levels(Side::Bid));
+        assert_eq!(asks, ob.all_levels(Side::Ask));
+
+        let empty = OrderBookImpl::new();
+        assert_eq!(empty.near_touch(5), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_spread_pct_and_bps() {
+        let ob = book_from(&[(9_900, 10)], &[(10_100, 10)]);
+        assert_eq!(ob.get_midpoint(), Some(10_000.0));
+        assert_eq!(ob.get_spread_pct(), Some(2.0));
+        assert_eq!(ob.get_spread_bps(), Some(200.0));
+
+        let odd = book_from(&[(100, 1)], &[(101, 1)]);
+        assert_eq!(odd.get_midpoint(), Some(100.5));
+        let pct = odd.get_spread_pct().unwrap();
+        assert!((pct - 100.0 / 100.5).abs() < 1e-12);
+        assert!((odd.get_spread_bps().unwrap() - pct * 100.0).abs() < 1e-9);
+
+        let one_sided = book_from(&[(100, 1)], &[]);
+        assert_eq!(one_sided.get_midpoint(), None);
+        assert_eq!(one_sided.get_spread_pct(), None);
+        assert_eq!(one_sided.get_spread_bps(), None);
+    }
+
+    #[test]
+    fn test_apply_update_delta_reconciles_totals() {
+        let mut ob = book_from(&[(100, 10)], &[(105, 20)]);
+        let updates = [
+            Update::Set {
+                price: 100,
+                quantity: 4,
+                side: Side::Bid,
+            },
+            Update::Set {
+                price: 99,
+                quantity: 7,
+                side: Side::Bid,
+            },
+            Update::Set {
+                price: 105,
+                quantity: 0,
+                side: Side::Ask,
+            },
+            Update::Remove {
+                price: 106,
+                side: Side::Ask,
+            },
+            Update::Set {
+                price: 107,
+                quantity: 9,
+                side: Side::Ask,
+            },
+        ];
+        let expected = [(-6, 0), (7, 0), (0, -20), (0, 0), (0, 9)];
+
+        for (update, expected) in updates.into_iter().zip(expected) {
+            let bid_before = ob.get_total_quantity(Side::Bid) as i64;
+            let ask_before = ob.get_total_quantity(Side::Ask) as i64;
+            let delta = ob.apply_update_delta(update);
+            assert_eq!(delta, expected);
+            assert_eq!(
+                ob.get_total_quantity(Side::Bid) as i64,
+                bid_before + delta.0
+            );
+            assert_eq!(
+                ob.get_total_quantity(Side::Ask) as i64,
+                ask_before + delta.1
+            );
+        }
+        assert!(ob.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_level_age_and_freshness() {
+        let mut ob = OrderBookImpl::new();
+        let set = |price, quantity, side| Update::Set {
+            price,
+            quantity,
+            side,
+        };
+
+        // Without tracking there are no ages and nothing is discounted
+        ob.apply_update_at(set(100, 10, Side::Bid), 1_000);
+        assert_eq!(ob.level_age(100, Side::Bid, 2_000), None);
+        assert_eq!(ob.freshness_weighted_depth(Side::Bid, 5, 2_000, 100), 10.0);
+
+        ob.set_timestamp_tracking(true);
+        assert!(ob.timestamp_tracking());
+        ob.apply_update_at(set(100, 10, Side::Bid), 1_000);
+        ob.apply_update_at(set(99, 8, Side::Bid), 1_500);
+        ob.apply_update_at(set(105, 4, Side::Ask), 1_900);
+
+        assert_eq!(ob.level_age(100, Side::Bid, 2_000), Some(1_000));
+        assert_eq!(ob.level_age(99, Side::Bid, 2_000), Some(500));
+        assert_eq!(ob.level_age(105, Side::Ask, 2_000), Some(100));
+        assert_eq!(ob.level_age(98, Side::Bid, 2_000), None);
+        assert_eq!(ob.level_age(-1, Side::Bid, 2_000), None);
+
+        // 100 is two half-lives old, 99 one half-life old
+        let depth = ob.freshness_weighted_depth(Side::Bid, 5, 2_000, 500);
+        assert!((depth - (10.0 * 0.25 + 8.0 * 0.5)).abs() < 1e-9);
+        let top = ob.freshness_weighted_depth(Side::Bid, 1, 2_000, 500);
+        assert!((top - 2.5).abs() < 1e-9);
+        assert_eq!(ob.freshness_weighted_depth(Side::Ask, 5, 1_900, 0), 4.0);
+        assert_eq!(ob.freshness_weighted_depth(Side::Ask, 5, 2_000, 0), 0.0);
+
+        // Removing a level hides its stale stamp
+        ob.apply_update_at(set(99, 0, Side::Bid), 2_100);
+        assert_eq!(ob.level_age(99, Side::Bid, 2_200), None);
+    }
+
+    #[test]
+    fn test_largest_gap() {
+        // Tight spread, but a 20-price hole behind the bid
+        let ob = book_from(&[(100, 5), (79, 5)], &[(102, 5), (103, 5), (110, 5)]);
+        assert_eq!(ob.largest_gap(), Some((80, 99)));
+
+        // The spread wins when it is the widest void, across a block boundary
+        let wide = book_from(&[(60, 5), (59, 5)], &[(140, 5)]);
+        assert_eq!(wide.largest_gap(), Some((61, 139)));
+
+        // Equal runs resolve to the lower one
+        let tie = book_from(&[(100, 5), (97, 5)], &[(103, 5)]);
+        assert_eq!(tie.largest_gap(), Some((98, 99)));
+
+        let dense = book_from(&[(100, 5), (99, 5)], &[(101, 5)]);
+        assert_eq!(dense.largest_gap(), None);
+
+        assert_eq!(book_from(&[(100, 5)], &[]).largest_gap(), None);
+        assert_eq!(book_from(&[(105, 5)], &[(100, 5)]).largest_gap(), None);
+    }
+
+    #[test]
+    fn test_consolidated_view() {
+        let venue_a = book_from(&[(100, 5)], &[(106, 3)]);
+        let venue_b = book_from(&[(101, 2)], &[(104, 8)]);
+        let venue_c = book_from(&[(101, 9)], &[]);
+        let empty = OrderBookImpl::new();
+
+        let view = ConsolidatedView::new(vec![&venue_a, &venue_b, &venue_c, &empty]);
+        assert_eq!(view.books().len(), 4);
+        assert_eq!(view.best_bid(), Some((1, 101, 2)));
+        assert_eq!(view.best_ask(), Some((1, 104, 8)));
+
+        let asks_only_in_a = ConsolidatedView::new(vec![&empty, &venue_c, &venue_a]);
+        assert_eq!(asks_only_in_a.best_ask(), Some((2, 106, 3)));
+
+        let none = ConsolidatedView::new(vec![&empty]);
+        assert_eq!(none.best_bid(), None);
+        assert_eq!(none.best_ask(), None);
+    }
+
+    #[test]
+    fn test_snapshot_blob_round_trip() {
+        let mut ob = book_from(&[(100, 10), (37, 2)], &[(105, 4), (199_999, 1)]);
+        ob.resync(&ob.all_levels(Side::Bid), &ob.all_levels(Side::Ask), 42);
+
+        let blob = ob.to_snapshot();
+        let restored = OrderBookImpl::from_snapshot(&blob).unwrap();
+        assert_eq!(restored.sequence(), 42);
+        assert_eq!(restored.fingerprint(), ob.fingerprint());
+        assert_eq!(restored.all_levels(Side::Ask), ob.all_levels(Side::Ask));
+        assert!(restored.check_invariants().is_ok());
+
+        let mut corrupt = blob.clone();
+        corrupt[40] ^= 1;
+        assert!(OrderBookImpl::from_snapshot(&corrupt).is_err());
+        assert!(OrderBookImpl::from_snapshot(&blob[..blob.len() - 3]).is_err());
+        assert!(OrderBookImpl::from_snapshot(&blob[..blob.len() - 8]).is_err());
+        assert!(OrderBookImpl::from_snapshot(b"nonsense").is_err());
+
+        let empty = OrderBookImpl::new();
+        let restored = OrderBookImpl::from_snapshot(&empty.to_snapshot()).unwrap();
+        assert_eq!(restored.get_best_bid(), None);
+    }
+
+    #[test]
+    fn test_snapshot_scheduler_rotates_and_loads_latest() {
+        let dir = std::env::temp_dir().join(format!("ob-snapshots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut ob = OrderBookImpl::new();
+        let mut scheduler = SnapshotScheduler::new(&dir, 3, None, 2).unwrap();
+        let mut written = 0;
+        for i in 0..10 {
+            ob.apply_update(Update::Set {
+                price: 100 + i,
+                quantity: 5,
+                side: Side::Bid,
+            });
+            if scheduler.on_update(&ob).unwrap() {
+                written += 1;
+            }
+        }
+        assert_eq!(written, 3);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Last snapshot was taken after the 9th update
+        let latest = load_latest(&dir).unwrap().unwrap();
+        assert_eq!(latest.get_best_bid(), Some(108));
+
+        // A corrupt newest file falls back to the previous one
+        let newest = scheduler.write_now(&ob).unwrap();
+        std::fs::write(&newest, b"torn").unwrap();
+        let latest = load_latest(&dir).unwrap().unwrap();
+        assert_eq!(latest.get_best_bid(), Some(108));
+
+        // A restarted scheduler keeps numbering after existing files
+        let mut restarted = SnapshotScheduler::new(&dir, 0, None, 2).unwrap();
+        restarted.write_now(&ob).unwrap();
+        assert_eq!(
+            load_latest(&dir).unwrap().unwrap().get_best_bid(),
+            Some(109)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(load_latest(&dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_post_sweep_spread() {
+        let ob = book_from(&[(100, 10), (98, 5)], &[(102, 4), (103, 6), (110, 10)]);
+
+        assert_eq!(ob.post_sweep_spread(Side::Ask, 0), Some(2));
+        assert_eq!(ob.post_sweep_spread(Side::Ask, 3), Some(2));
+        // Exactly clearing a level moves the touch on
+        assert_eq!(ob.post_sweep_spread(Side::Ask, 4), Some(3));
+        assert_eq!(ob.post_sweep_spread(Side::Ask, 10), Some(10));
+        assert_eq!(ob.post_sweep_spread(Side::Ask, 20), None);
+
+        assert_eq!(ob.post_sweep_spread(Side::Bid, 12), Some(4));
+        assert_eq!(ob.post_sweep_spread(Side::Bid, 15), None);
+
+        let one_sided = book_from(&[(100, 10)], &[]);
+        assert_eq!(one_sided.post_sweep_spread(Side::Bid, 1), None);
+    }
+
+    #[test]
+    fn test_float_and_u32_quantity_books() {
+        let mut ob = OrderBookImpl::<f64>::empty();
+        ob.set(100, 1.5, Side::Bid);
+        ob.set(99, 0.25, Side::Bid);
+        ob.set(102, 0.125, Side::Ask);
+        ob.set(103, 2.0, Side::Ask);
+
+        assert_eq!(ob.get_best_bid_with_qty(), Some((100, 1.5)));
+        assert_eq!(ob.get_spread(), Some(2));
+        assert_eq!(ob.get_quantity_at(99, Side::Bid), Some(0.25));
+        assert_eq!(ob.get_total_quantity(Side::Bid), 1.75);
+        assert_eq!(ob.total_notional(Side::Ask), 102.0 * 0.125 + 103.0 * 2.0);
+        assert_eq!(
+            ob.get_top_levels(Side::Ask, 5),
+            vec![(102, 0.125), (103, 2.0)]
+        );
+
+        // 0.0 is the empty sentinel, so a zero Set and a Remove both clear the level
+        ob.set(102, 0.0, Side::Ask);
+        ob.remove(100, Side::Bid);
+        assert_eq!(ob.get_best_ask(), Some(103));
+        assert_eq!(ob.get_best_bid_with_qty(), Some((99, 0.25)));
+        assert_eq!(ob.level_count(Side::Ask), 1);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 0.25);
+
+        // Buffered writes keep their float quantities across a halt
+        ob.halt();
+        ob.set(101, 0.5, Side::Bid);
+        assert_eq!(ob.get_best_bid(), Some(99));
+        assert_eq!(ob.resume(), 1);
+        assert_eq!(ob.get_best_bid_with_qty(), Some((101, 0.5)));
+
+        let mut small = OrderBookImpl::<u32>::empty();
+        small.set(50, u32::MAX - 7, Side::Bid);
+        small.set(49, 7, Side::Bid);
+        assert_eq!(
+            small.total_notional(Side::Bid),
+            50 * (u32::MAX - 7) as u128 + 49 * 7
+        );
+        assert_eq!(
+            small.levels(Side::Bid).collect::<Vec<_>>(),
+            vec![(50, u32::MAX - 7), (49, 7)]
+        );
+        small.clear();
+        assert_eq!(small.get_best_bid(), None);
+        assert_eq!(small.get_total_quantity(Side::Bid), 0);
+    }
+
+    #[test]
+    fn test_raw_best_sentinels() {
+        let mut ob = book_from(&[(100, 1)], &[]);
+        assert_eq!(ob.raw_best_bid(), 100);
+        assert_eq!(ob.raw_best_ask(), -1);
+
+        ob.apply_update(Update::Set {
+            price: 0,
+            quantity: 1,
+            side: Side::Ask,
+        });
+        ob.apply_update(Update::Remove {
+            price: 100,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.raw_best_ask(), 0);
+        assert_eq!(ob.raw_best_bid(), -1);
+    }
+
+    #[test]
+    fn test_time_weighted_avg_spread() {
+        let mut ob = book_from(&[(100, 5)], &[(102, 5)]);
+        ob.record_spread(0);
+        assert!(ob.spread_history().is_empty());
+
+        ob.set_spread_history(16);
+        ob.record_spread(0);
+        assert_eq!(ob.time_weighted_avg_spread(), None);
+
+        // Spread 2 for 900ns, then 6 for 100ns
+        ob.apply_update_at(
+            Update::Set {
+                price: 106,
+                quantity: 5,
+                side: Side::Ask,
+            },
+            500,
+        );
+        assert_eq!(ob.spread_history().len(), 1);
+        ob.apply_update_at(
+            Update::Remove {
+                price: 102,
+                side: Side::Ask,
+            },
+            900,
+        );
+        ob.record_spread(1_000);
+        assert_eq!(ob.spread_history().len(), 3);
+        let avg = ob.time_weighted_avg_spread().unwrap();
+        assert!((avg - 2.4).abs() < 1e-12);
+
+        // Time with an empty side carries no weight
+        ob.apply_update_at(
+            Update::Remove {
+                price: 100,
+                side: Side::Bid,
+            },
+            1_000,
+        );
+        ob.record_spread(5_000);
+        assert!((ob.time_weighted_avg_spread().unwrap() - 2.4).abs() < 1e-12);
+
+        // Capacity bounds the window
+        ob.set_spread_history(2);
+        ob.record_spread(0);
+        ob.record_spread(1);
+        ob.record_spread(2);
+        assert_eq!(ob.spread_history().len(), 2);
+        assert_eq!(ob.spread_history()[0].0, 1);
+    }
+
+    #[test]
+    fn test_quantity_f64_precision() {
+        let limit: Quantity = 1 << 53;
+        assert!(quantity_fits_f64(0));
+        assert!(quantity_fits_f64(limit));
+        assert!(quantity_fits_f64(1 << 60));
+        assert!(!quantity_fits_f64(limit + 1));
+        assert!(!quantity_fits_f64(Quantity::MAX));
+        assert_eq!(quantity_to_f64_checked(limit), limit as f64);
+
+        let exact = book_from(&[(100, limit)], &[]);
+        assert_eq!(exact.side_vwap(Side::Bid), Some(100.0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "loses precision")]
+    fn test_vwap_flags_lossy_quantity() {
+        let ob = book_from(&[(100, (1 << 53) + 1)], &[]);
+        ob.vwap(Side::Bid, 1);
+    }
+
+    #[test]
+    fn test_remove_prices_matches_individual_removes() {
+        let mut rng = XorShift64::new(11);
+        let mut batched = OrderBookImpl::new();
+        let mut single = OrderBookImpl::new();
+        for _ in 0..400 {
+            let side = if rng.next_u64() & 1 == 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let price = match side {
+                Side::Bid => rng.range(900, 1_000),
+                Side::Ask => rng.range(1_001, 1_100),
+            } as Price;
+            let update = Update::Set {
+                price,
+                quantity: rng.range(1, 50),
+                side,
+            };
+            batched.apply_update(update.clone());
+            single.apply_update(update);
+        }
+
+        // Includes both bests, empty levels and a duplicate
+        let mut cancels = vec![
+            (batched.get_best_bid().unwrap(), Side::Bid),
+            (batched.get_best_ask().unwrap(), Side::Ask),
+            (500, Side::Bid),
+            (batched.get_best_ask().unwrap(), Side::Ask),
+        ];
+        for _ in 0..60 {
+            cancels.push((rng.range(900, 1_000) as Price, Side::Bid));
+            cancels.push((rng.range(1_001, 1_100) as Price, Side::Ask));
+        }
+
+        batched.set_flicker_tracking(true);
+        batched.remove_prices(&cancels);
+        for &(price, side) in &cancels {
+            single.apply_update(Update::Remove { price, side });
+        }
+
+        assert_eq!(batched.touch_flicker_count(), 2);
+        assert_eq!(batched.fingerprint(), single.fingerprint());
+        assert_eq!(
+            batched.get_best_bid_with_qty(),
+            single.get_best_bid_with_qty()
+        );
+        assert_eq!(
+            batched.get_best_ask_with_qty(),
+            single.get_best_ask_with_qty()
+        );
+        assert!(batched.check_invariants().is_ok());
+
+        // Clearing a whole side leaves it empty
+        let bids: Vec<(Price, Side)> = batched
+            .levels(Side::Bid)
+            .map(|(price, _)| (price, Side::Bid))
+            .collect();
+        batched.remove_prices(&bids);
+        assert_eq!(batched.get_best_bid(), None);
+        assert!(batched.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_avg_liquidity_distance() {
+        let ob = book_from(&[(100, 10), (98, 5), (90, 5)], &[(101, 7)]);
+        // (0 * 10 + 2 * 5 + 10 * 5) / 20
+        assert_eq!(ob.avg_liquidity_distance(Side::Bid), Some(3.0));
+        assert_eq!(ob.avg_liquidity_distance(Side::Ask), Some(0.0));
+
+        let one_sided = book_from(&[(100, 10)], &[]);
+        assert_eq!(one_sided.avg_liquidity_distance(Side::Ask), None);
+    }
+
+    #[test]
+    fn test_midpoint_realized_vol() {
+        let mut ob = book_from(&[(99, 5)], &[(101, 5)]);
+        assert_eq!(ob.midpoint_realized_vol(), None);
+        ob.set_mid_history(8);
+
+        // Mid 100 -> 120 -> 100; each step moves both sides, so only the
+        // second update of a pair changes the mid. A deep bid records nothing
+        let steps = [
+            (Side::Bid, 50, 5, 1),
+            (Side::Ask, 101, 0, 2),
+            (Side::Bid, 119, 5, 3),
+            (Side::Ask, 121, 5, 4),
+            (Side::Bid, 99, 0, 5),
+            (Side::Ask, 101, 5, 6),
+            (Side::Bid, 119, 0, 7),
+            (Side::Bid, 99, 5, 8),
+        ];
+        ob.record_midpoint();
+        assert_eq!(ob.midpoint_realized_vol(), None);
+        for (side, price, quantity, now_ns) in steps {
+            ob.apply_update_at(
+                Update::Set {
+                    price,
+                    quantity,
+                    side,
+                },
+                now_ns,
+            );
+        }
+        let mids: Vec<f64> = ob.mid_history().iter().copied().collect();
+        assert_eq!(mids.first(), Some(&100.0));
+        assert_eq!(mids.last(), Some(&100.0));
+        assert!(mids.contains(&120.0));
+
+        let mut history = book_from(&[(99, 5)], &[(101, 5)]);
+        history.set_mid_history(4);
+        history.record_midpoint();
+        history.apply_update(Update::Set {
+            price: 120,
+            quantity: 5,
+            side: Side::Ask,
+        });
+        history.apply_update(Update::Remove {
+            price: 101,
+            side: Side::Ask,
+        });
+        // 99/120 -> mid 109.5
+        history.record_midpoint();
+        history.apply_update(Update::Set {
+            price: 101,
+            quantity: 5,
+            side: Side::Ask,
+        });
+        history.record_midpoint();
+        // Returns +r and -r: mean 0, deviation r
+        let r = (109.5f64 / 100.0).ln();
+        let vol = history.midpoint_realized_vol().unwrap();
+        assert!((vol - r).abs() < 1e-12);
+
+        history.set_mid_history(0);
+        history.record_midpoint();
+        assert!(history.mid_history().is_empty());
+    }
+
+    /// Reference book that only implements the required trait methods, so
+    /// the trait's default methods run unmodified
+    #[derive(Default)]
+    struct MapBook {
+        bids: std::collections::BTreeMap<Price, Quantity>,
+        asks: std::collections::BTreeMap<Price, Quantity>,
+    }
+
+    impl MapBook {
+        fn side(&self, side: Side) -> &std::collections::BTreeMap<Price, Quantity> {
+            match side {
+                Side::Bid => &self.bids,
+                Side::Ask => &self.asks,
+            }
+        }
+    }
+
+    impl OrderBook for MapBook {
+        fn new() -> Self {
+            MapBook::default()
+        }
+
+        fn apply_update(&mut self, update: Update) {
+            let (price, quantity, side) = match update {
+                Update::Set {
+                    price,
+                    quantity,
+                    side,
+                } => (price, quantity, side),
+                Update::Remove { price, side } => (price, 0, side),
+            };
+            let levels = match side {
+                Side::Bid => &mut self.bids,
+                Side::Ask => &mut self.asks,
+            };
+            if quantity == 0 {
+                levels.remove(&price);
+            } else {
+                levels.insert(price, quantity);
+            }
+        }
+
+        fn get_spread(&self) -> Option<Price> {
+            Some(self.get_best_ask()? - self.get_best_bid()?)
+        }
+
+        fn get_best_bid(&self) -> Option<Price> {
+            self.bids.keys().next_back().copied()
+        }
+
+        fn get_best_ask(&self) -> Option<Price> {
+            self.asks.keys().next().copied()
+        }
+
+        fn get_quantity_at(&self, price: Price, side: Side) -> Option<Quantity> {
+            self.side(side).get(&price).copied()
+        }
+
+        fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
+            match side {
+                Side::Bid => self
+                    .bids
+                    .iter()
+                    .rev()
+                    .take(n)
+                    .map(|(&p, &q)| (p, q))
+                    .collect(),
+                Side::Ask => self.asks.iter().take(n).map(|(&p, &q)| (p, q)).collect(),
+            }
+        }
+
+        fn get_total_quantity(&self, side: Side) -> Quantity {
+            self.side(side).values().sum()
+        }
+    }
+
+    #[test]
+    fn test_liquidity_ahead_and_fill_probability() {
+        let ob = book_from(&[(100, 10), (98, 30), (95, 60)], &[(102, 20), (105, 40)]);
+
+        assert_eq!(ob.liquidity_ahead(96, Side::Bid), 40);
+        assert_eq!(ob.liquidity_ahead(98, Side::Bid), 10);
+        assert_eq!(ob.liquidity_ahead(101, Side::Bid), 0);
+        assert_eq!(ob.liquidity_ahead(106, Side::Ask), 60);
+        assert_eq!(ob.liquidity_ahead(102, Side::Ask), 0);
+        assert_eq!(OrderBookImpl::new().liquidity_ahead(50, Side::Ask), 0);
+
+        // The trait default (per-price probing) agrees with the block-sum path
+        let mut probe = MapBook::default();
+        for (price, quantity) in [(100, 10), (98, 30), (95, 60)] {
+            probe.set(price, quantity, Side::Bid);
+        }
+        for price in 90..=101 {
+            assert_eq!(
+                probe.liquidity_ahead(price, Side::Bid),
+                ob.liquidity_ahead(price, Side::Bid)
+            );
+        }
+
+        // Joining the best bid: queue is the 10 already there
+        assert_eq!(ob.fill_probability(100, Side::Bid, 5), 0.5);
+        assert_eq!(ob.fill_probability(100, Side::Bid, 50), 1.0);
+        // Improving the touch: nothing to clear
+        assert_eq!(ob.fill_probability(101, Side::Bid, 1), 1.0);
+        assert_eq!(ob.fill_probability(101, Side::Ask, 1), 1.0);
+        assert_eq!(ob.fill_probability(102, Side::Ask, 5), 0.25);
+        // Behind the touch, no volume, or an empty side
+        assert_eq!(ob.fill_probability(98, Side::Bid, 1_000), 0.0);
+        assert_eq!(ob.fill_probability(105, Side::Ask, 1_000), 0.0);
+        assert_eq!(ob.fill_probability(100, Side::Bid, 0), 0.0);
+        assert_eq!(
+            OrderBookImpl::new().fill_probability(100, Side::Bid, 5),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_bbo_snapshot() {
+        assert_eq!(std::mem::size_of::<BboSnapshot>(), 40);
+        assert_eq!(std::mem::align_of::<BboSnapshot>(), 8);
+
+        let mut ob = OrderBookImpl::new();
+        assert_eq!(
+            ob.bbo_snapshot(),
+            BboSnapshot {
+                best_bid: -1,
+                bid_qty: 0,
+                best_ask: -1,
+                ask_qty: 0,
+                seq: 0,
+            }
+        );
+
+        ob.apply_update_seq(
+            Update::Set {
+                price: 100,
+                quantity: 7,
+                side: Side::Bid,
+            },
+            1,
+        )
+        .unwrap();
+        ob.apply_update_seq(
+            Update::Set {
+                price: 103,
+                quantity: 4,
+                side: Side::Ask,
+            },
+            2,
+        )
+        .unwrap();
+        assert_eq!(
+            ob.bbo_snapshot(),
+            BboSnapshot {
+                best_bid: 100,
+                bid_qty: 7,
+                best_ask: 103,
+                ask_qty: 4,
+                seq: 2,
+            }
+        );
+
+        ob.apply_update_seq(
+            Update::Remove {
+                price: 100,
+                side: Side::Bid,
+            },
+            3,
+        )
+        .unwrap();
+        let snapshot = ob.bbo_snapshot();
+        assert_eq!(
+            (snapshot.best_bid, snapshot.bid_qty, snapshot.seq),
+            (-1, 0, 3)
+        );
+    }
+
+    #[test]
+    fn test_execute_market_order_report() {
+        let mut ob = book_from(&[(99, 10)], &[(101, 5), (102, 10), (104, 20)]);
+
+        let report = ob.execute_market_order(Side::Ask, 12);
+        assert_eq!(
+            report,
+            ExecutionReport {
+                fills: vec![(101, 5), (102, 7)],
+                filled: 12,
+                requested: 12,
+                avg_price: (101.0 * 5.0 + 102.0 * 7.0) / 12.0,
+            }
+        );
+        assert_eq!(ob.get_best_ask(), Some(102));
+        assert_eq!(ob.get_quantity_at(102, Side::Ask), Some(3));
+        assert_eq!(ob.get_total_quantity(Side::Ask), 23);
+
+        // Partial: the side runs dry before the request is met
+        let report = ob.execute_market_order(Side::Ask, 100);
+        assert_eq!(report.fills, vec![(102, 3), (104, 20)]);
+        assert_eq!((report.filled, report.requested), (23, 100));
+        assert!(report.filled < report.requested);
+        assert_eq!(ob.get_best_ask(), None);
+        assert!(ob.check_invariants().is_ok());
+
+        let report = ob.execute_market_order(Side::Ask, 1);
+        assert_eq!((report.filled, report.avg_price), (0, 0.0));
+        assert!(report.fills.is_empty());
+
+        ob.halt();
+        let report = ob.execute_market_order(Side::Bid, 5);
+        assert_eq!(report.filled, 0);
+        assert_eq!(ob.get_quantity_at(99, Side::Bid), Some(10));
+    }
+
+    #[test]
+    fn test_cost_curve() {
+        let ob = book_from(&[(100, 10), (98, 10)], &[(101, 5), (103, 5), (107, 10)]);
+
+        assert_eq!(
+            ob.cost_curve(Side::Ask, 12),
+            vec![
+                (5, 101.0),
+                (10, 102.0),
+                (12, (505.0 + 515.0 + 214.0) / 12.0)
+            ]
+        );
+        assert_eq!(
+            ob.cost_curve(Side::Bid, 1_000),
+            vec![(10, 100.0), (20, 99.0)]
+        );
+        assert!(ob.cost_curve(Side::Bid, 0).is_empty());
+        assert!(OrderBookImpl::new().cost_curve(Side::Ask, 10).is_empty());
+
+        let asks = ob.cost_curve(Side::Ask, u64::MAX);
+        assert!(asks.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert_eq!(asks.last().unwrap().0, ob.get_total_quantity(Side::Ask));
+    }
+
+    #[test]
+    fn test_current_spread_percentile() {
+        let mut ob = book_from(&[(100, 5)], &[(102, 5)]);
+        ob.set_spread_history(16);
+        assert_eq!(ob.current_spread_percentile(), None);
+
+        // Recorded spreads 2, 4, 6, 8 plus one sample with the ask side empty
+        for (now_ns, ask) in [(1, 102), (2, 104), (3, 106), (4, 108)] {
+            ob.apply_update(Update::Set {
+                price: ask,
+                quantity: 5,
+                side: Side::Ask,
+            });
+            ob.record_spread(now_ns);
+            ob.apply_update(Update::Remove {
+                price: ask,
+                side: Side::Ask,
+            });
+        }
+        ob.record_spread(5);
+        assert_eq!(ob.current_spread_percentile(), None);
+
+        let mut set_ask = |price| {
+            ob.apply_update(Update::Set {
+                price,
+                quantity: 5,
+                side: Side::Ask,
+            });
+            let percentile = ob.current_spread_percentile();
+            ob.apply_update(Update::Remove {
+                price,
+                side: Side::Ask,
+            });
+            percentile
+        };
+        assert_eq!(set_ask(120), Some(1.0));
+        assert_eq!(set_ask(101), Some(0.0));
+        // Wider than 2 and 4, tied with 6: (2 + 0.5) / 4
+        assert_eq!(set_ask(106), Some(0.625));
+    }
+
+    #[test]
+    fn test_apply_all_and_try_apply_all() {
+        let set = |price, side| Update::Set {
+            price,
+            quantity: 5,
+            side,
+        };
+        let mut ob = OrderBookImpl::new();
+        let applied = ob.apply_all(vec![
+            set(100, Side::Bid),
+            set(101, Side::Bid),
+            set(103, Side::Ask),
+            Update::Remove {
+                price: 101,
+                side: Side::Bid,
+            },
+        ]);
+        assert_eq!(applied, 4);
+        assert_eq!(ob.get_spread(), Some(3));
+        assert_eq!(ob.apply_all(std::iter::empty()), 0);
+
+        let lines = ["B 99", "A 104", "A x", "B 98"];
+        let parsed = lines.iter().map(|line| {
+            let (side, price) = line.split_once(' ').unwrap();
+            let price: Price = price
+                .parse()
+                .map_err(|_| format!("bad price in {line:?}"))?;
+            let side = if side == "B" { Side::Bid } else { Side::Ask };
+            Ok(set(price, side))
+        });
+        assert_eq!(
+            ob.try_apply_all(parsed),
+            Err::<usize, String>("bad price in \"A x\"".to_string())
+        );
+        // Applied up to the error, nothing after it
+        assert_eq!(ob.get_quantity_at(104, Side::Ask), Some(5));
+        assert_eq!(ob.get_quantity_at(98, Side::Bid), None);
+
+        let ok: Vec<Result<Update, String>> = vec![Ok(set(98, Side::Bid))];
+        assert_eq!(ob.try_apply_all(ok), Ok(1));
+    }
+
+    #[test]
+    fn test_kernel_weighted_mid() {
+        let ob = book_from(&[(99, 10), (90, 100)], &[(101, 10), (110, 10)]);
+
+        // Tight kernel: only the touch counts, symmetric around mid 100
+        let tight = ob.kernel_weighted_mid(1.0).unwrap();
+        assert!((tight - 100.0).abs() < 1e-6);
+
+        // Wide kernel tends to the whole-book VWAP, pulled toward the deep bid
+        let vwap = (99.0 * 10.0 + 90.0 * 100.0 + 101.0 * 10.0 + 110.0 * 10.0) / 130.0;
+        let wide = ob.kernel_weighted_mid(1e6).unwrap();
+        assert!((wide - vwap).abs() < 1e-3);
+        assert!(ob.kernel_weighted_mid(10.0).unwrap() < 100.0);
+
+        assert_eq!(ob.kernel_weighted_mid(0.0), None);
+        assert_eq!(ob.kernel_weighted_mid(f64::NAN), None);
+        assert_eq!(book_from(&[(99, 10)], &[]).kernel_weighted_mid(5.0), None);
+    }
+
+    #[test]
+    fn test_load_verified_snapshot() {
+        let bids = [(100, 5), (99, 7)];
+        let asks = [(102, 3)];
+
+        // CRC-32 of "100:5:102:3:99:7" and "10231005997"
+        let interleaved = 0x9fda_e369;
+        let concatenated = 0x102c_f436;
+        let mut reference = book_from(&bids, &asks);
+        assert_eq!(reference.checksum(10), interleaved);
+        assert_eq!(reference.checksum(2), interleaved);
+        assert_ne!(reference.checksum(1), interleaved);
+        reference.set_checksum_format(ChecksumFormat::Concatenated);
+        assert_eq!(reference.checksum(10), concatenated);
+
+        let mut ob = book_from(&[(50, 1)], &[(60, 1)]);
+        let before = ob.fingerprint();
+        assert_eq!(
+            ob.load_verified_snapshot(&bids, &asks, concatenated, 10),
+            Err(OrderBookError::ChecksumMismatch {
+                expected: concatenated,
+                actual: interleaved,
+            })
+        );
+        // Rolled back to the previous levels
+        assert_eq!(ob.fingerprint(), before);
+        assert_eq!(ob.get_best_bid(), Some(50));
+
+        assert_eq!(
+            ob.load_verified_snapshot(&bids, &asks, interleaved, 10),
+            Ok(())
+        );
+        assert_eq!(ob.all_levels(Side::Bid), bids);
+        assert_eq!(ob.all_levels(Side::Ask), asks);
+        assert!(ob.check_invariants().is_ok());
+
+        ob.set_checksum_format(ChecksumFormat::Concatenated);
+        assert_eq!(ob.checksum_format(), ChecksumFormat::Concatenated);
+        assert_eq!(
+            ob.load_verified_snapshot(&bids, &asks, concatenated, 10),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_overlay_book() {
+        let ob = book_from(&[(100, 5), (98, 5)], &[(103, 4), (105, 6)]);
+        let before = ob.fingerprint();
+
+        let mut overlay = OverlayBook::new(&ob);
+        assert_eq!(
+            overlay.get_top_levels(Side::Bid, 10),
+            ob.all_levels(Side::Bid)
+        );
+        assert_eq!(overlay.get_spread(), ob.get_spread());
+
+        overlay.set_implied(101, 2, Side::Bid);
+        overlay.set_implied(98, 3, Side::Bid);
+        overlay.set_implied(104, 1, Side::Ask);
+        overlay.set_implied(110, 0, Side::Ask);
+
+        assert_eq!(overlay.get_best_bid(), Some(101));
+        assert_eq!(overlay.get_best_ask(), Some(103));
+        assert_eq!(overlay.get_spread(), Some(2));
+        assert_eq!(overlay.get_quantity_at(98, Side::Bid), Some(8));
+        assert_eq!(overlay.get_quantity_at(104, Side::Ask), Some(1));
+        assert_eq!(overlay.get_quantity_at(110, Side::Ask), None);
+        assert_eq!(
+            overlay.get_top_levels(Side::Bid, 10),
+            vec![(101, 2), (100, 5), (98, 8)]
+        );
+        assert_eq!(
+            overlay.get_top_levels(Side::Ask, 2),
+            vec![(103, 4), (104, 1)]
+        );
+        assert_eq!(overlay.get_total_quantity(Side::Bid), 15);
+
+        // Implied-only side, and the real book is untouched
+        let empty = OrderBookImpl::new();
+        let mut implied_only = OverlayBook::new(&empty);
+        implied_only.set_implied(99, 1, Side::Ask);
+        assert_eq!(implied_only.get_best_ask(), Some(99));
+        assert_eq!(implied_only.get_spread(), None);
+
+        overlay.clear_implied();
+        assert_eq!(overlay.get_best_bid(), Some(100));
+        assert_eq!(ob.fingerprint(), before);
+    }
+
+    #[test]
+    fn test_diff_and_diff_size() {
+        let ours = book_from(&[(100, 5), (99, 5), (90, 1)], &[(101, 5), (200, 2)]);
+        let target = book_from(&[(100, 5), (99, 7), (95, 3)], &[(101, 5), (300, 1)]);
+
+        let updates = ours.diff(&target);
+        assert_eq!(
+            updates,
+            vec![
+                Update::Remove {
+                    price: 90,
+                    side: Side::Bid,
+                },
+                Update::Set {
+                    price: 95,
+                    quantity: 3,
+                    side: Side::Bid,
+                },
+                Update::Set {
+                    price: 99,
+                    quantity: 7,
+                    side: Side::Bid,
+                },
+                Update::Remove {
+                    price: 200,
+                    side: Side::Ask,
+                },
+                Update::Set {
+                    price: 300,
+                    quantity: 1,
+                    side: Side::Ask,
+                },
+            ]
+        );
+        assert_eq!(ours.diff_size(&target), updates.len());
+        assert_eq!(ours.diff_size(&ours), 0);
+        assert_eq!(target.diff_size(&OrderBookImpl::new()), 5);
+
+        let mut replica = book_from(&[(100, 5), (99, 5), (90, 1)], &[(101, 5), (200, 2)]);
+        replica.apply_all(updates);
+        assert_eq!(replica.fingerprint(), target.fingerprint());
+    }
+
+    #[test]
+    fn test_update_queue_spsc() {
+        let queue = UpdateQueue::new(5);
+        assert_eq!(queue.capacity(), 8);
+
+        let set = |price: Price, quantity| Update::Set {
+            price,
+            quantity,
+            side: if price < 1_000 { Side::Bid } else { Side::Ask },
+        };
+        for i in 0..8 {
+            assert!(queue.push(set(100 + i, 1)));
+        }
+        assert!(!queue.push(set(200, 1)));
+        let mut ob = OrderBookImpl::new();
+        assert_eq!(queue.drain_into(&mut ob), 8);
+        assert_eq!(ob.get_best_bid(), Some(107));
+        assert!(queue.is_empty());
+
+        // Feed thread and book thread; the result must match applying the
+        // stream in order on one thread
+        let stream: Vec<Update> = (0..20_000u64)
+            .map(|i| set((i * 7919 % 2_000) as Price, i % 5))
+            .collect();
+        let mut expected = OrderBookImpl::new();
+        expected.apply_all(stream.iter().cloned());
+
+        let queue = UpdateQueue::new(1_024);
+        let mut book = OrderBookImpl::new();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for update in &stream {
+                    while !queue.push(update.clone()) {
+                        std::hint::spin_loop();
+                    }
+                }
+            });
+            let mut applied = 0;
+            while applied < stream.len() {
+                applied += queue.drain_into(&mut book);
+            }
+        });
+        assert_eq!(book.fingerprint(), expected.fingerprint());
+        assert!(book.check_invariants().is_ok());
+
+        // Undrained updates are dropped with the queue
+        let leftover = UpdateQueue::new(4);
+        assert!(leftover.push(set(1, 1)));
+        assert_eq!(leftover.len(), 1);
+        drop(leftover);
+    }
+
+    #[test]
+    fn test_center_of_mass() {
+        let ob = book_from(&[(100, 10), (90, 30)], &[(110, 20)]);
+
+        assert_eq!(ob.center_of_mass(Side::Bid), Some(92.5));
+        assert_eq!(ob.center_of_mass(Side::Ask), Some(110.0));
+        assert_eq!(ob.book_center(), Some((1_000.0 + 2_700.0 + 2_200.0) / 60.0));
+
+        let one_sided = book_from(&[], &[(110, 20)]);
+        assert_eq!(one_sided.center_of_mass(Side::Bid), None);
+        assert_eq!(one_sided.book_center(), Some(110.0));
+        assert_eq!(OrderBookImpl::new().book_center(), None);
+    }
+
+    #[test]
+    fn test_update_from_wire() {
+        let mut be = vec![0b01];
+        be.extend_from_slice(&10_050i64.to_be_bytes());
+        be.extend_from_slice(&7u64.to_be_bytes());
+        be.push(0b10);
+        be.extend_from_slice(&9_990i64.to_be_bytes());
+
+        let (first, used) = Update::from_wire_be(&be).unwrap();
+        assert_eq!(
+            (first, used),
+            (
+                Update::Set {
+                    price: 10_050,
+                    quantity: 7,
+                    side: Side::Ask,
+                },
+                17
+            )
+        );
+        let (second, used) = Update::from_wire_be(&be[17..]).unwrap();
+        assert_eq!(
+            (second, used),
+            (
+                Update::Remove {
+                    price: 9_990,
+                    side: Side::Bid,
+                },
+                9
+            )
+        );
+
+        let mut le = vec![0b00];
+        le.extend_from_slice(&10_050i64.to_le_bytes());
+        le.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(
+            Update::from_wire_le(&le),
+            Some((
+                Update::Set {
+                    price: 10_050,
+                    quantity: 7,
+                    side: Side::Bid,
+                },
+                17
+            ))
+        );
+
+        // Truncation at every length and unknown header bits are rejected
+        for len in 0..17 {
+            assert_eq!(Update::from_wire_le(&le[..len]), None);
+        }
+        assert_eq!(Update::from_wire_be(&be[17..25]), None);
+        le[0] = 0b100;
+        assert_eq!(Update::from_wire_le(&le), None);
+    }
+
+    #[test]
+    fn test_imbalance_gradient() {
+        // Imbalance by rank: 0, 0.5, 0.6 (bids deepen relative to asks)
+        let ob = book_from(
+            &[(100, 10), (99, 30), (98, 40)],
+            &[(101, 10), (102, 10), (103, 10)],
+        );
+        let slope = ob.imbalance_gradient(3).unwrap();
+        assert!((slope - 0.3).abs() < 1e-12);
+
+        let mirrored = book_from(
+            &[(100, 10), (99, 10), (98, 10)],
+            &[(101, 10), (102, 30), (103, 40)],
+        );
+        assert!((mirrored.imbalance_gradient(3).unwrap() + 0.3).abs() < 1e-12);
+
+        assert_eq!(ob.imbalance_gradient(1), None);
+        assert_eq!(ob.imbalance_gradient(4), None);
+        assert_eq!(
+            book_from(&[(100, 1), (99, 1)], &[(101, 1)]).imbalance_gradient(2),
+            None
+        );
+    }
+
+    #[test]
+    fn test_apply_batch_and_recent_levels() {
+        // Longer than the prefetch distance, with repeated prices, so order matters
+        let updates: Vec<Update> = (0..100u64)
+            .map(|i| Update::Set {
+                price: 1_000 + (i * 7919 % 13) as Price,
+                quantity: i + 1,
+                side: if i % 3 == 0 { Side::Ask } else { Side::Bid },
+            })
+            .collect();
+        let mut batched = OrderBookImpl::new();
+        let mut single = OrderBookImpl::new();
+        batched.apply_batch(&updates);
+        for update in updates {
+            single.apply_update(update);
+        }
+        for side in [Side::Bid, Side::Ask] {
+            assert_eq!(
+                batched.get_top_levels(side, 20),
+                single.get_top_levels(side, 20)
+            );
+        }
+
+        // Extreme prices are ignored, including by the prefetch looking ahead
+        // at them, on an inverted book where the slot math would overflow
+        let mut inverted: OrderBookImpl = OrderBookImpl::with_inverted_index(true);
+        let extremes: Vec<Update> = [i64::MIN, i64::MIN + 1, -1, i64::MAX, 5]
+            .into_iter()
+            .cycle()
+            .take(4 * 8)
+            .flat_map(|price| {
+                [
+                    Update::Set {
+                        price,
+                        quantity: 3,
+                        side: Side::Bid,
+                    },
+                    Update::Remove {
+                        price,
+                        side: Side::Ask,
+                    },
+                ]
+            })
+            .collect();
+        inverted.apply_batch(&extremes);
+        assert_eq!(inverted.all_levels(Side::Bid), vec![(5, 3)]);
+        assert_eq!(inverted.level_count(Side::Ask), 0);
+        inverted.check_invariants().unwrap();
+
+        let mut ob = OrderBookImpl::new();
+        ob.set(100, 1, Side::Bid);
+        assert!(ob.recent_levels().is_empty());
+        ob.set_recent_tracking(3);
+        ob.set(101, 1, Side::Bid);
+        ob.set(102, 1, Side::Ask);
+        ob.remove(101, Side::Bid);
+        ob.apply_update(Update::Set {
+            price: 103,
+            quantity: 2,
+            side: Side::Ask,
+        });
+        let mut recent = ob.recent_levels().to_vec();
+        recent.sort_by_key(|&(price, _)| price);
+        assert_eq!(
+            recent,
+            vec![(101, Side::Bid), (102, Side::Ask), (103, Side::Ask)]
+        );
+        ob.prefetch_recent();
+        assert_eq!(ob.get_best_ask(), Some(102));
+
+        // Held updates aren't touched yet; tracking survives the halt
+        ob.halt();
+        ob.set(105, 1, Side::Ask);
+        assert!(!ob.recent_levels().contains(&(105, Side::Ask)));
+        assert_eq!(ob.resume(), 1);
+        ob.set(106, 1, Side::Ask);
+        assert!(ob.recent_levels().contains(&(106, Side::Ask)));
+
+        ob.set_recent_tracking(0);
+        ob.set(104, 1, Side::Ask);
+        assert!(ob.recent_levels().is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_stream() {
+        let set = |price, quantity, side| Update::Set {
+            price,
+            quantity,
+            side,
+        };
+        let stream = vec![
+            set(100, 5, Side::Bid),
+            set(-1, 5, Side::Bid),
+            set(100, Quantity::MAX - 10, Side::Ask),
+            set(101, 11, Side::Ask),
+            // Resizing a level only adds the difference
+            set(100, Quantity::MAX - 1, Side::Ask),
+            Update::Remove {
+                price: 100,
+                side: Side::Ask,
+            },
+            set(101, 11, Side::Ask),
+            Update::Remove {
+                price: 500,
+                side: Side::Bid,
+            },
+        ];
+        let (kept, rejected) = sanitize_stream(&stream, (0, 200));
+        assert_eq!(
+            rejected,
+            vec![
+                (
+                    1,
+                    OrderBookError::PriceOutOfRange {
+                        price: -1,
+                        low: 0,
+                        high: 200
+                    }
+                ),
+                (
+                    3,
+                    OrderBookError::QuantityOverflow {
+                        quantity: 11,
+                        side: Side::Ask
+                    }
+                ),
+                (
+                    7,
+                    OrderBookError::PriceOutOfRange {
+                        price: 500,
+                        low: 0,
+                        high: 200
+                    }
+                ),
+            ]
+        );
+        assert_eq!(kept.len(), 5);
+
+        let mut ob = OrderBookImpl::new();
+        ob.apply_all(kept);
+        assert_eq!(ob.get_total_quantity(Side::Ask), 11);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 5);
+        assert!(ob.check_invariants().is_ok());
+        assert_eq!(
+            OrderBookError::PriceOutOfRange {
+                price: -1,
+                low: 0,
+                high: 200
+            }
+            .to_string(),
+            "price -1 outside [0, 200]"
+        );
+    }
+
+    #[test]
+    fn test_next_worse_level() {
+        for inverted in [false, true] {
+            let mut ob = OrderBookImpl::with_inverted_index(inverted);
+            for (price, side) in [
+                (0, Side::Bid),
+                (100, Side::Bid),
+                (163, Side::Bid),
+                (164, Side::Bid),
+                (300, Side::Ask),
+                (428, Side::Ask),
+                (200_000, Side::Ask),
+            ] {
+                ob.set(price, price as Quantity + 1, side);
+            }
+            assert_eq!(ob.next_worse_level(164, Side::Bid), Some((163, 164)));
+            assert_eq!(ob.next_worse_level(163, Side::Bid), Some((100, 101)));
+            assert_eq!(ob.next_worse_level(100, Side::Bid), Some((0, 1)));
+            assert_eq!(ob.next_worse_level(0, Side::Bid), None);
+            assert_eq!(ob.next_worse_level(1_000_000, Side::Bid), Some((164, 165)));
+            assert_eq!(ob.next_worse_level(250, Side::Ask), Some((300, 301)));
+            assert_eq!(ob.next_worse_level(300, Side::Ask), Some((428, 429)));
+            assert_eq!(
+                ob.next_worse_level(428, Side::Ask),
+                Some((200_000, 200_001))
+            );
+            assert_eq!(ob.next_worse_level(200_000, Side::Ask), None);
+            assert_eq!(ob.next_worse_level(-7, Side::Ask), Some((300, 301)));
+
+            let beyond: Vec<Price> = ob.levels_after(Side::Bid, 164).map(|(p, _)| p).collect();
+            assert_eq!(beyond, vec![163, 100, 0]);
+        }
+    }
+
+    #[test]
+    fn test_max_quantity_within_slippage() {
+        let ob = book_from(&[(100, 10), (99, 10)], &[(100, 10), (101, 10), (110, 10)]);
+        // 1% of 100 allows 1 price unit of VWAP drift: all of 101, then one
+        // lot at 110 (VWAP 100.95; a second would give 101.36)
+        assert_eq!(ob.max_quantity_within_slippage(Side::Ask, 100.0), 21);
+        let (_, vwap) = *ob.cost_curve(Side::Ask, 21).last().unwrap();
+        assert!(vwap <= 101.0);
+        assert!(ob.cost_curve(Side::Ask, 22).last().unwrap().1 > 101.0);
+
+        // Landing exactly on the budget is allowed
+        assert_eq!(ob.max_quantity_within_slippage(Side::Bid, 50.0), 20);
+        assert_eq!(ob.max_quantity_within_slippage(Side::Bid, 49.0), 19);
+        assert_eq!(ob.max_quantity_within_slippage(Side::Ask, 0.0), 10);
+        assert_eq!(ob.max_quantity_within_slippage(Side::Ask, -5.0), 10);
+        assert_eq!(ob.max_quantity_within_slippage(Side::Ask, 1e9), 30);
+        assert_eq!(
+            OrderBookImpl::new().max_quantity_within_slippage(Side::Bid, 10.0),
+            0
+        );
+    }
+
+    #[test]
+    fn test_bitmask_summary() {
+        let mut ob: OrderBookImpl = OrderBookImpl::new();
+        for price in [0, 5, 63, 64, 1_000] {
+            ob.set(price, 1, Side::Bid);
+        }
+        ob.set(2_000, 1, Side::Ask);
+        assert_eq!(ob.bitmask_summary(Side::Bid), vec![(0, 3), (1, 1), (15, 1)]);
+        assert_eq!(ob.bitmask_summary(Side::Ask), vec![(31, 1)]);
+        ob.remove(64, Side::Bid);
+        assert_eq!(ob.bitmask_summary(Side::Bid), vec![(0, 3), (15, 1)]);
+
+        let mut inverted: OrderBookImpl = OrderBookImpl::with_inverted_index(true);
+        inverted.set(ob.max_price() - 1, 1, Side::Ask);
+        assert_eq!(inverted.bitmask_summary(Side::Ask), vec![(0, 1)]);
+        assert!(inverted.bitmask_summary(Side::Bid).is_empty());
+    }
+
+    #[test]
+    fn test_apply_and_publish() {
+        let mut ob = book_from(&[(100, 5), (99, 5)], &[(102, 7)]);
+        let set = |price, quantity, side| Update::Set {
+            price,
+            quantity,
+            side,
+        };
+        // Behind the touch: no BBO change
+        assert_eq!(ob.apply_and_publish(set(98, 3, Side::Bid)), None);
+        assert_eq!(ob.apply_and_publish(set(99, 9, Side::Bid)), None);
+        // Size change at the touch publishes post-update sizes
+        assert_eq!(
+            ob.apply_and_publish(set(100, 6, Side::Bid)),
+            Some(BboSnapshot {
+                best_bid: 100,
+                bid_qty: 6,
+                best_ask: 102,
+                ask_qty: 7,
+                seq: 0,
+            })
+        );
+        let published = ob
+            .apply_and_publish(Update::Remove {
+                price: 102,
+                side: Side::Ask,
+            })
+            .unwrap();
+        assert_eq!((published.best_ask, published.ask_qty), (-1, 0));
+        // Same quantity again is not a change
+        assert_eq!(ob.apply_and_publish(set(100, 6, Side::Bid)), None);
+
+        ob.halt();
+        assert_eq!(ob.apply_and_publish(set(101, 1, Side::Bid)), None);
+        ob.resume();
+        assert_eq!(ob.get_best_bid(), Some(101));
+    }
+
+    #[test]
+    fn test_display_rows() {
+        let ob = book_from(
+            &[(100, 5), (99, 6), (97, 7)],
+            &[(103, 1), (104, 2), (110, 3)],
+        );
+        assert_eq!(
+            ob.display_rows(2),
+            vec![
+                DisplayRow::Ask { price: 104, qty: 2 },
+                DisplayRow::Ask { price: 103, qty: 1 },
+                DisplayRow::Mid {
+                    price: 101.5,
+                    spread: 3
+                },
+                DisplayRow::Bid { price: 100, qty: 5 },
+                DisplayRow::Bid { price: 99, qty: 6 },
+            ]
+        );
+        assert_eq!(
+            ob.display_rows(0),
+            vec![DisplayRow::Mid {
+                price: 101.5,
+                spread: 3
+            }]
+        );
+
+        // No mid row with a side empty
+        let one_sided = book_from(&[(100, 5)], &[]);
+        assert_eq!(
+            one_sided.display_rows(5),
+            vec![DisplayRow::Bid { price: 100, qty: 5 }]
+        );
+    }
+
+    #[test]
+    fn test_estimated_queue_position() {
+        let mut ob = book_from(&[(100, 5), (99, 6), (98, 7)], &[(103, 1), (104, 2)]);
+        assert_eq!(ob.estimated_queue_position(99, Side::Bid, 4), 9);
+        // Capped at what rests at the level
+        assert_eq!(ob.estimated_queue_position(99, Side::Bid, 50), 11);
+        assert_eq!(ob.estimated_queue_position(100, Side::Bid, 0), 0);
+        assert_eq!(ob.estimated_queue_position(104, Side::Ask, 1), 2);
+        // An empty level: only the better prices count
+        assert_eq!(ob.estimated_queue_position(105, Side::Ask, 3), 3);
+        ob.remove(100, Side::Bid);
+        assert_eq!(ob.estimated_queue_position(99, Side::Bid, 4), 4);
+    }
+
+    #[test]
+    fn test_has_liquidity_in_range() {
+        for inverted in [false, true] {
+            let mut ob: OrderBookImpl = OrderBookImpl::with_inverted_index(inverted);
+            ob.set(130, 1, Side::Bid);
+            ob.set(1_000, 1, Side::Ask);
+            assert!(ob.has_liquidity_in_range(Side::Bid, 130, 130));
+            assert!(ob.has_liquidity_in_range(Side::Bid, 0, 200_000));
+            // Edges of the same and neighbouring blocks
+            assert!(!ob.has_liquidity_in_range(Side::Bid, 131, 191));
+            assert!(!ob.has_liquidity_in_range(Side::Bid, 64, 129));
+            assert!(!ob.has_liquidity_in_range(Side::Ask, 130, 130));
+            assert!(ob.has_liquidity_in_range(Side::Ask, 999, 5_000));
+            assert!(!ob.has_liquidity_in_range(Side::Ask, 1_001, 200_000));
+            // Empty and out-of-range bands
+            assert!(!ob.has_liquidity_in_range(Side::Bid, 140, 120));
+            assert!(!ob.has_liquidity_in_range(Side::Bid, -50, -1));
+            assert!(ob.has_liquidity_in_range(Side::Ask, -50, i64::MAX));
+        }
+    }
+
+    #[test]
+    fn test_usable_notional() {
+        let ob = book_from(&[(100, 5), (99, 6), (90, 7)], &[(103, 1), (105, 2)]);
+        assert_eq!(ob.usable_notional(Side::Bid, 0), 500);
+        assert_eq!(ob.usable_notional(Side::Bid, 1), 500 + 594);
+        assert_eq!(ob.usable_notional(Side::Bid, 9), 500 + 594);
+        assert_eq!(ob.usable_notional(Side::Bid, 10), 500 + 594 + 630);
+        assert_eq!(
+            ob.usable_notional(Side::Bid, u64::MAX),
+            ob.total_notional(Side::Bid)
+        );
+        assert_eq!(ob.usable_notional(Side::Ask, 2), 103 + 210);
+        assert_eq!(book_from(&[], &[]).usable_notional(Side::Ask, 100), 0);
+    }
+
+    #[test]
+    fn test_book_skew() {
+        // Mid 100: bids 1 and 3 away (weighted 2), asks 1 and 5 away (weighted 3)
+        let ob = book_from(&[(99, 10), (97, 10)], &[(101, 10), (105, 10)]);
+        assert!((ob.book_skew(2).unwrap() - 0.2).abs() < 1e-12);
+        // Top level only: symmetric around the mid
+        assert_eq!(ob.book_skew(1), Some(0.0));
+        assert!(ob.book_skew(10).unwrap() > 0.0);
+
+        let mirrored = book_from(&[(99, 10), (95, 10)], &[(101, 10), (103, 10)]);
+        assert!((mirrored.book_skew(2).unwrap() + 0.2).abs() < 1e-12);
+
+        assert_eq!(ob.book_skew(0), None);
+        assert_eq!(book_from(&[(99, 1)], &[]).book_skew(3), None);
+    }
+
+    #[test]
+    fn test_replay_with_callback() {
+        let updates = vec![
+            Update::Set {
+                price: 100,
+                quantity: 5,
+                side: Side::Bid,
+            },
+            Update::Set {
+                price: 103,
+                quantity: 2,
+                side: Side::Ask,
+            },
+            Update::Set {
+                price: 101,
+                quantity: 1,
+                side: Side::Bid,
+            },
+            Update::Remove {
+                price: 101,
+                side: Side::Bid,
+            },
+        ];
+        let mut events = Vec::new();
+        let mut book = OrderBookImpl::new();
+        replay_with_callback(&updates, &mut book, |ob, index| {
+            events.push((index, ob.get_best_bid(), ob.get_spread()));
+        });
+        assert_eq!(
+            events,
+            vec![
+                (0, Some(100), None),
+                (1, Some(100), Some(3)),
+                (2, Some(101), Some(2)),
+                (3, Some(100), Some(3)),
+            ]
+        );
+
+        // Works for any OrderBook
+        let mut map_book = MapBook::new();
+        let mut count = 0;
+        replay_with_callback(&updates, &mut map_book, |_, _| count += 1);
+        assert_eq!(count, 4);
+        assert_eq!(map_book.get_best_bid(), Some(100));
+    }
+
+    #[test]
+    fn test_update_level_reports_delta() {
+        let mut ob = OrderBookImpl::new();
+        assert_eq!(ob.update_level(100, 10, Side::Bid), 10);
+        assert_eq!(ob.update_level(100, 4, Side::Bid), -6);
+        assert_eq!(ob.update_level(100, 4, Side::Bid), 0);
+        assert_eq!(ob.update_level(105, 3, Side::Ask), 3);
+        assert_eq!(ob.update_level(100, 0, Side::Bid), -4);
+        assert_eq!(ob.get_best_bid(), None);
+        assert_eq!(ob.update_level(100, 0, Side::Bid), 0);
+
+        ob.halt();
+        assert_eq!(ob.update_level(105, 9, Side::Ask), 0);
+        ob.resume();
+        assert_eq!(ob.get_quantity_at(105, Side::Ask), Some(9));
+        assert!(ob.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_sticky_best_defers_rescan() {
+        let mut ob = book_from(&[(100, 5), (90, 6)], &[(110, 7), (120, 8)]);
+        ob.set_sticky_best(2);
+
+        // Flicker out and back: the stale best is replaced by the real one
+        ob.remove(100, Side::Bid);
+        assert!(ob.is_best_stale(Side::Bid));
+        assert_eq!(ob.get_best_bid_with_qty(), Some((100, 0)));
+        assert!(ob.check_invariants().is_ok());
+        ob.set(100, 4, Side::Bid);
+        assert!(!ob.is_best_stale(Side::Bid));
+        assert_eq!(ob.get_best_bid_with_qty(), Some((100, 4)));
+
+        // Left stale for two more updates, rescanned on the third
+        ob.remove(110, Side::Ask);
+        ob.set(95, 1, Side::Bid);
+        ob.set(300, 1, Side::Ask);
+        assert_eq!(ob.get_best_ask(), Some(110));
+        assert!(ob.is_best_stale(Side::Ask));
+        ob.set(96, 1, Side::Bid);
+        assert!(!ob.is_best_stale(Side::Ask));
+        assert_eq!(ob.get_best_ask_with_qty(), Some((120, 8)));
+
+        // A forced refresh is always exact, and the checked read sees no anomaly
+        ob.remove(100, Side::Bid);
+        assert_eq!(ob.get_spread(), Some(20));
+        ob.refresh_best();
+        assert_eq!(ob.get_best_bid_with_qty(), Some((96, 1)));
+        ob.remove(96, Side::Bid);
+        assert_eq!(ob.get_best_bid_checked(), Some((95, 1)));
+        assert_eq!(ob.anomaly_count(), 0);
+
+        // Off again: removals rescan immediately
+        ob.set_sticky_best(0);
+        ob.remove(95, Side::Bid);
+        assert_eq!(ob.get_best_bid(), Some(90));
+        assert!(!ob.is_best_stale(Side::Bid));
+    }
+
+    #[test]
+    fn test_sticky_best_matches_plain_book_after_refresh() {
+        let mut rng = rust_3::workload::XorShift64::new(9);
+        let mut sticky = OrderBookImpl::new();
+        let mut plain = OrderBookImpl::new();
+        sticky.set_sticky_best(3);
+        for i in 0..1_000 {
+            let price = rng.range(990, 1_010) as Price;
+            let side = if rng.next_u64() & 1 == 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let quantity = if rng.range(0, 2) == 0 {
+                0
+            } else {
+                rng.range(1, 50)
+            };
+            sticky.set(price, quantity, side);
+            plain.set(price, quantity, side);
+            assert!(sticky.check_invariants().is_ok(), "step {i}");
+            if i % 7 == 0 {
+                sticky.refresh_best();
+                assert_eq!(sticky.bbo_snapshot(), plain.bbo_snapshot(), "step {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_prices_ignored() {
+        // Run under `cargo miri test out_of_range` to check no access strays
+        // past the arrays
+        for inverted in [false, true] {
+            let mut ob: OrderBookImpl = OrderBookImpl::with_inverted_index(inverted);
+            ob.apply_update(Update::Set {
+                price: 100,
+                quantity: 7,
+                side: Side::Bid,
+            });
+            ob.apply_update(Update::Set {
+                price: 200,
+                quantity: 9,
+                side: Side::Ask,
+            });
+            let max = ob.max_price();
+
+            for price in [max, -5] {
+                for side in [Side::Bid, Side::Ask] {
+                    ob.apply_update(Update::Set {
+                        price,
+                        quantity: 50,
+                        side,
+                    });
+                    ob.apply_update(Update::Remove { price, side });
+                    ob.set(price, 50, side);
+                    assert_eq!(
+                        ob.apply_update_delta(Update::Set {
+                            price,
+                            quantity: 50,
+                            side,
+                        }),
+                        (0, 0)
+                    );
+                    ob.remove_prices(&[(price, side)]);
+                    assert_eq!(ob.get_quantity_at(price, side), None);
+                }
+            }
+
+            assert_eq!(ob.get_best_bid(), Some(100));
+            assert_eq!(ob.get_best_ask(), Some(200));
+            assert_eq!(ob.get_total_quantity(Side::Bid), 7);
+            assert_eq!(ob.get_total_quantity(Side::Ask), 9);
+            assert_eq!(ob.level_count(Side::Bid) + ob.level_count(Side::Ask), 2);
+            ob.check_invariants().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fill_cost() {
+        let ob = book_from(&[(100, 10), (98, 10)], &[(101, 5), (103, 5), (107, 10)]);
+
+        // Buy 12: 5 @ 101 + 5 @ 103 + 2 @ 107
+        let (filled, notional) = ob.fill_cost(Side::Ask, 12).unwrap();
+        assert_eq!((filled, notional), (12, 505 + 515 + 214));
+        assert_eq!(notional as f64 / filled as f64, 1234.0 / 12.0);
+
+        // Sell 15: 10 @ 100 + 5 @ 98
+        assert_eq!(ob.fill_cost(Side::Bid, 15), Some((15, 1490)));
+
+        // Partial: only 20 asks rest
+        let (filled, notional) = ob.fill_cost(Side::Ask, 100).unwrap();
+        assert_eq!((filled, notional), (20, 505 + 515 + 1070));
+        assert_eq!(notional as f64 / filled as f64, 104.5);
+
+        assert_eq!(ob.fill_cost(Side::Bid, 0), Some((0, 0)));
+        assert_eq!(OrderBookImpl::new().fill_cost(Side::Ask, 10), None);
+
+        // The trait default, fetching levels in batches, agrees past a batch
+        let mut probe = MapBook::default();
+        let mut deep: OrderBookImpl = OrderBookImpl::new();
+        for i in 0..40 {
+            probe.set(1_000 + i * 3, 1 + i as Quantity, Side::Ask);
+            deep.set(1_000 + i * 3, 1 + i as Quantity, Side::Ask);
+        }
+        for quantity in [0, 1, 136, 137, 500, 820, 10_000] {
+            assert_eq!(
+                probe.fill_cost(Side::Ask, quantity),
+                deep.fill_cost(Side::Ask, quantity)
+            );
+        }
+        assert_eq!(probe.fill_cost(Side::Bid, 5), None);
+    }
+
+    #[test]
+    fn test_crossing_set_clears_crossed_levels() {
+        let mut ob = book_from(
+            &[(149_900, 10), (149_800, 10)],
+            &[(150_000, 5), (150_050, 6), (150_200, 7)],
+        );
+
+        // A bid through the best ask takes out every ask priced below it
+        ob.apply_update(Update::Set {
+            price: 150_100,
+            quantity: 3,
+            side: Side::Bid,
+        });
+        assert_eq!(ob.get_best_bid(), Some(150_100));
+        assert_eq!(ob.get_best_ask(), Some(150_200));
+        assert_eq!(ob.get_spread(), Some(100));
+        assert_eq!(ob.get_quantity_at(150_000, Side::Ask), None);
+        assert_eq!(ob.get_quantity_at(150_050, Side::Ask), None);
+        assert_eq!(ob.get_total_quantity(Side::Ask), 7);
+        ob.check_invariants().unwrap();
+
+        // Same from the ask side; a locked book (spread 0) is left alone
+        ob.set(149_800, 4, Side::Ask);
+        assert_eq!(ob.get_top_levels(Side::Bid, 5), vec![(149_800, 10)]);
+        assert_eq!(ob.get_spread(), Some(0));
+        ob.check_invariants().unwrap();
+
+        // Emptying the whole opposite side
+        ob.set(200_000, 1, Side::Bid);
+        assert_eq!(ob.get_best_ask(), None);
+        assert_eq!(ob.get_spread(), None);
+        ob.check_invariants().unwrap();
+
+        // A stale sticky best is no exemption: an ask under it rescans the bids
+        let mut ob = book_from(&[(100, 1), (90, 1)], &[(120, 1)]);
+        ob.set_sticky_best(8);
+        ob.remove(100, Side::Bid);
+        assert!(ob.is_best_stale(Side::Bid));
+        ob.set(95, 1, Side::Ask);
+        assert!(!ob.is_best_stale(Side::Bid));
+        assert_eq!(ob.get_spread(), Some(5));
+        ob.check_invariants().unwrap();
+
+        // An L3 order crossing the book takes the crossed orders with it
+        let mut l3 = L3Book::new();
+        l3.add_order(1, 100, 5, Side::Ask).unwrap();
+        l3.add_order(2, 101, 5, Side::Ask).unwrap();
+        l3.add_order(3, 103, 5, Side::Ask).unwrap();
+        l3.add_order(4, 102, 2, Side::Bid).unwrap();
+        assert_eq!(l3.order_count(), 2);
+        assert_eq!(l3.cancel_order(1), Err(OrderBookError::UnknownOrder(1)));
+        l3.cancel_order(3).unwrap();
+        assert_eq!(l3.book().get_total_quantity(Side::Ask), 0);
+
+        // Random crossing traffic never yields a negative spread
+        let mut rng = XorShift64::new(7);
+        let mut ob: OrderBookImpl = OrderBookImpl::new();
+        for _ in 0..2_000 {
+            let side = if rng.next_u64() & 1 == 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            ob.set(rng.range(1_000, 1_100) as Price, rng.range(0, 5), side);
+            assert!(ob.get_spread().is_none_or(|spread| spread >= 0));
+        }
+        ob.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_with_capacity_sizes_the_price_range() {
+        for max_price in [256, 4_000_001] {
+            let top = max_price as Price - 1;
+            let mut ob: OrderBookImpl = OrderBookImpl::with_capacity(max_price);
+            assert_eq!(ob.max_price(), max_price as Price);
+
+            // Levels in the first, a middle and the last block, far apart
+            let bids = [(top / 2 - 1, 4), (63, 2), (0, 1)];
+            let asks = [(top / 2 + 64, 5), (top - 1, 6), (top, 7)];
+            for &(price, quantity) in &bids {
+                ob.set(price, quantity, Side::Bid);
+            }
+            for &(price, quantity) in &asks {
+                ob.set(price, quantity, Side::Ask);
+            }
+            // Just past the range is ignored
+            ob.set(max_price as Price, 9, Side::Ask);
+            ob.set(-1, 9, Side::Bid);
+
+            assert_eq!(ob.get_top_levels(Side::Bid, 10), bids.to_vec());
+            assert_eq!(ob.get_top_levels(Side::Ask, 10), asks.to_vec());
+            assert_eq!(ob.get_spread(), Some(65));
+            assert_eq!(ob.next_worse_level(top - 1, Side::Ask), Some((top, 7)));
+            assert_eq!(ob.quantity_in_band(Side::Ask, 0, Price::MAX), 18);
+            assert_eq!(ob.liquidity_ahead(top, Side::Ask), 11);
+            ob.check_invariants().unwrap();
+
+            // Rescans walk back across the range after the bests go
+            ob.remove(top / 2 - 1, Side::Bid);
+            ob.remove(top / 2 + 64, Side::Ask);
+            assert_eq!(ob.get_best_bid(), Some(63));
+            assert_eq!(ob.get_best_ask(), Some(top - 1));
+            ob.check_invariants().unwrap();
+
+            ob.clear();
+            assert_eq!(ob.level_count(Side::Bid) + ob.level_count(Side::Ask), 0);
+            ob.check_invariants().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_imbalance() {
+        // Symmetric book
+        let ob = book_from(&[(100, 10), (99, 20)], &[(101, 10), (102, 20)]);
+        assert_eq!(ob.imbalance(1), Some(0.0));
+        assert_eq!(ob.imbalance(5), Some(0.0));
+
+        // Depth 1 is the best quantities alone; deeper levels shift it
+        let ob = book_from(&[(100, 30), (99, 10)], &[(101, 10), (102, 50)]);
+        assert_eq!(ob.imbalance(1), Some(0.5));
+        assert_eq!(ob.imbalance(2), Some(-20.0 / 100.0));
+
+        // Fully one-sided
+        assert_eq!(book_from(&[(100, 5)], &[]).imbalance(3), Some(1.0));
+        assert_eq!(book_from(&[], &[(101, 5)]).imbalance(3), Some(-1.0));
+
+        // No data: empty book or zero depth
+        assert_eq!(OrderBookImpl::new().imbalance(5), None);
+        assert_eq!(ob.imbalance(0), None);
+
+        // The trait default agrees
+        let mut probe = MapBook::default();
+        for (price, quantity) in [(100, 30), (99, 10)] {
+            probe.set(price, quantity, Side::Bid);
+        }
+        for (price, quantity) in [(101, 10), (102, 50)] {
+            probe.set(price, quantity, Side::Ask);
+        }
+        for depth in 0..4 {
+            assert_eq!(probe.imbalance(depth), ob.imbalance(depth));
+        }
+    }
+
+    #[test]
+    fn test_mid_price_and_microprice() {
+        let ob = book_from(&[(100, 10)], &[(104, 10)]);
+        assert_eq!(ob.mid_price(), Some(102));
+        // Equal sizes: the plain midpoint
+        assert_eq!(ob.microprice(), Some(102.0));
+
+        // Odd spread rounds the integer mid down
+        assert_eq!(book_from(&[(100, 1)], &[(103, 1)]).mid_price(), Some(101));
+
+        // Less size on the ask: fair value leans up toward it
+        let ob = book_from(&[(100, 30)], &[(104, 10)]);
+        assert_eq!(ob.microprice(), Some((10.0 * 100.0 + 30.0 * 104.0) / 40.0));
+        assert!(ob.microprice().unwrap() > 102.0);
+        // And down toward a thin bid
+        let ob = book_from(&[(100, 5), (99, 100)], &[(104, 45)]);
+        assert_eq!(ob.microprice(), Some(100.4));
+
+        assert_eq!(book_from(&[(100, 5)], &[]).mid_price(), None);
+        assert_eq!(book_from(&[], &[(104, 5)]).microprice(), None);
+
+        // The trait defaults agree
+        let mut probe = MapBook::default();
+        probe.set(100, 5, Side::Bid);
+        probe.set(99, 100, Side::Bid);
+        probe.set(104, 45, Side::Ask);
+        assert_eq!(probe.mid_price(), ob.mid_price());
+        assert_eq!(probe.microprice(), ob.microprice());
+        assert_eq!(MapBook::default().microprice(), None);
+    }
+
+    // Every public query on `a` and `b` agrees, probing `prices`
+    fn assert_same_observable_state(a: &OrderBookImpl, b: &OrderBookImpl, prices: &[Price]) {
+        assert_eq!(a.get_best_bid(), b.get_best_bid());
+        assert_eq!(a.get_best_ask(), b.get_best_ask());
+        assert_eq!(a.get_spread(), b.get_spread());
+        assert_eq!(a.bbo_snapshot(), b.bbo_snapshot());
+        assert_eq!(a.sequence(), b.sequence());
+        assert_eq!(a.max_price(), b.max_price());
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.mid_price(), b.mid_price());
+        assert_eq!(a.microprice(), b.microprice());
+        assert_eq!(a.imbalance(10), b.imbalance(10));
+        for side in [Side::Bid, Side::Ask] {
+            assert_eq!(a.get_total_quantity(side), b.get_total_quantity(side));
+            assert_eq!(a.total_notional(side), b.total_notional(side));
+            assert_eq!(a.level_count(side), b.level_count(side));
+            assert_eq!(a.get_top_levels(side, 25), b.get_top_levels(side, 25));
+            assert_eq!(a.all_levels(side), b.all_levels(side));
+            assert_eq!(a.fill_cost(side, 1_000), b.fill_cost(side, 1_000));
+            for &price in prices {
+                assert_eq!(
+                    a.get_quantity_at(price, side),
+                    b.get_quantity_at(price, side)
+                );
+                assert_eq!(
+                    a.liquidity_ahead(price, side),
+                    b.liquidity_ahead(price, side)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore_round_trip() {
+        let mut rng = XorShift64::new(508);
+        let mut ob: OrderBookImpl = OrderBookImpl::new();
+        let mut prices = Vec::new();
+        for seq in 1..=3_000 {
+            let price = rng.range(9_000, 11_000) as Price;
+            let side = if rng.next_u64() & 1 == 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let update = if rng.range(0, 4) == 0 {
+                Update::Remove { price, side }
+            } else {
+                Update::Set {
+                    price,
+                    quantity: rng.range(0, 500),
+                    side,
+                }
+            };
+            ob.apply_update_seq(update, seq).unwrap();
+            prices.push(price);
+        }
+
+        let snapshot = ob.snapshot();
+        assert_eq!(snapshot.seq, 3_000);
+        assert_eq!(
+            snapshot.bids.len() + snapshot.asks.len(),
+            ob.level_count(Side::Bid) + ob.level_count(Side::Ask)
+        );
+        let restored = OrderBookImpl::restore(&snapshot);
+        assert_same_observable_state(&ob, &restored, &prices);
+        restored.check_invariants().unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+
+        // The price range comes back with the levels
+        let mut small: OrderBookImpl = OrderBookImpl::with_capacity(256);
+        small.set(10, 4, Side::Bid);
+        small.set(255, 6, Side::Ask);
+        assert_eq!(
+            small.snapshot(),
+            BookSnapshot {
+                max_price: 256,
+                seq: 0,
+                bids: vec![(10, 4)],
+                asks: vec![(255, 6)],
+                total_bid_quantity: 4,
+                total_ask_quantity: 6,
+            }
+        );
+        let restored = OrderBookImpl::restore(&small.snapshot());
+        assert_same_observable_state(&small, &restored, &[10, 255]);
+        assert_eq!(
+            OrderBookImpl::restore(&OrderBookImpl::new().snapshot()).snapshot(),
+            OrderBookImpl::new().snapshot()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_json_round_trip() {
+        let mut ob: OrderBookImpl = book_from(&[(100, 10), (98, 3)], &[(101, 7)]);
+        ob.apply_update_seq(
+            Update::Set {
+                price: 102,
+                quantity: 4,
+                side: Side::Ask,
+            },
+            1,
+        )
+        .unwrap();
+
+        let json = serde_json::to_string(&ob.snapshot()).unwrap();
+        let snapshot: BookSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, ob.snapshot());
+        let restored = OrderBookImpl::restore(&snapshot);
+        assert_same_observable_state(&ob, &restored, &[98, 100, 101, 102]);
+    }
+
+    #[test]
+    fn test_sticky_best_refreshes_through_update_level() {
+        let mut ob = book_from(&[(100, 5), (90, 6)], &[(110, 7)]);
+        ob.set_sticky_best(3);
+        ob.remove(100, Side::Bid);
+        assert!(ob.is_best_stale(Side::Bid));
+
+        // Delta updates count toward the limit like `set` does
+        for i in 0..20 {
+            ob.update_level(200 + i, 1, Side::Ask);
+        }
+        assert!(!ob.is_best_stale(Side::Bid));
+        assert_eq!(ob.get_best_bid_with_qty(), Some((90, 6)));
+
+        // A delta set through the stale best makes it real again
+        ob.remove(90, Side::Bid);
+        assert!(ob.is_best_stale(Side::Bid));
+        assert_eq!(ob.update_level(95, 2, Side::Bid), 2);
+        assert!(!ob.is_best_stale(Side::Bid));
+        assert_eq!(ob.get_best_bid_with_qty(), Some((95, 2)));
+        assert!(ob.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_snapshot_blob_keeps_range_and_layout() {
+        let books: [(OrderBookImpl, bool); 3] = [
+            (OrderBookImpl::with_capacity(128), false),
+            (OrderBookImpl::with_capacity(1_000_003), false),
+            (OrderBookImpl::with_inverted_index(true), true),
+        ];
+        for (mut ob, inverted) in books {
+            let max_price = ob.max_price();
+            let top = max_price - 1;
+            ob.resync(&[(3, 4), (0, 1)], &[(top, 2), (top - 1, 5)], 7);
+
+            let restored = OrderBookImpl::from_snapshot(&ob.to_snapshot()).unwrap();
+            assert_eq!(restored.max_price(), max_price);
+            assert_eq!(restored.is_inverted(), inverted);
+            assert_eq!(restored.sequence(), 7);
+            assert_eq!(restored.fingerprint(), ob.fingerprint());
+            assert_eq!(restored.get_best_ask(), Some(top - 1));
+            assert!(restored.check_invariants().is_ok());
+        }
+
+        // A level past the stored range is rejected, not dropped
+        let small: OrderBookImpl = OrderBookImpl::with_capacity(128);
+        let mut blob = small.to_snapshot();
+        blob[16..24].copy_from_slice(&0u64.to_le_bytes());
+        assert!(OrderBookImpl::from_snapshot(&blob).is_err());
+        let mut blob = book_from(&[(100, 1)], &[]).to_snapshot();
+        blob[16..24].copy_from_slice(&100u64.to_le_bytes());
+        assert!(OrderBookImpl::from_snapshot(&blob).is_err());
+
+        // An oversized range fails as InvalidData instead of being allocated
+        for max_price in [(1 << 28) + 1, 1 << 40, 1 << 61, u64::MAX] {
+            let mut blob = small.to_snapshot();
+            blob[16..24].copy_from_slice(&max_price.to_le_bytes());
+            let err = OrderBookImpl::from_snapshot(&blob).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        // Version 1 blobs still load with the default range
+        let ob = book_from(&[(100, 10)], &[(199_999, 1)]);
+        let mut v1 = ob.to_snapshot();
+        v1.drain(16..32);
+        v1[..8].copy_from_slice(b"OBSNAP01");
+        let restored = OrderBookImpl::from_snapshot(&v1).unwrap();
+        assert_eq!(restored.max_price(), 200_001);
+        assert!(!restored.is_inverted());
+        assert_eq!(restored.fingerprint(), ob.fingerprint());
+    }
+
+    #[test]
+    fn test_l3_rejects_out_of_range_and_overflowing_orders() {
+        let mut l3 = L3Book::new();
+        let high = l3.book().max_price() - 1;
+        for price in [-1, high + 1, Price::MAX] {
+            assert_eq!(
+                l3.add_order(1, price, 5, Side::Bid),
+                Err(OrderBookError::PriceOutOfRange {
+                    price,
+                    low: 0,
+                    high
+                })
+            );
+        }
+        assert_eq!(l3.order_count(), 0);
+
+        // Sizes above i64::MAX aggregate exactly
+        let big = i64::MAX as Quantity + 10;
+        l3.add_order(1, 100, big, Side::Bid).unwrap();
+        l3.add_order(2, 100, 5, Side::Bid).unwrap();
+        assert_eq!(l3.book().get_quantity_at(100, Side::Bid), Some(big + 5));
+        assert_eq!(
+            l3.add_order(3, 100, Quantity::MAX - big, Side::Bid),
+            Err(OrderBookError::QuantityOverflow {
+                quantity: Quantity::MAX - big,
+                side: Side::Bid
+            })
+        );
+        assert_eq!(
+            l3.modify_order(2, Quantity::MAX),
+            Err(OrderBookError::QuantityOverflow {
+                quantity: Quantity::MAX,
+                side: Side::Bid
+            })
+        );
+        assert_eq!(l3.order(2), Some((100, 5, Side::Bid)));
+        assert_eq!(l3.order(3), None);
+        l3.modify_order(2, 7).unwrap();
+        l3.cancel_order(1).unwrap();
+        assert_eq!(l3.book().get_quantity_at(100, Side::Bid), Some(7));
+    }
+
+    #[test]
+    fn test_side_totals_wrap_instead_of_panicking() {
+        let mut ob: OrderBookImpl = OrderBookImpl::new();
+        ob.set(100, Quantity::MAX, Side::Bid);
+        ob.set(101, 5, Side::Bid);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 4);
+        ob.set(101, 7, Side::Bid);
+        ob.set(5_000, Quantity::MAX, Side::Ask);
+        ob.set(5_001, 1, Side::Ask);
+        assert!(ob.check_invariants().is_ok());
+
+        // Totals come back exactly once the side fits again
+        ob.remove(100, Side::Bid);
+        ob.remove(5_001, Side::Ask);
+        assert_eq!(ob.get_total_quantity(Side::Bid), 7);
+        assert_eq!(ob.get_total_quantity(Side::Ask), Quantity::MAX);
+        assert!(ob.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_depth_readers_saturate_on_huge_books() {
+        const HALF: Quantity = Quantity::MAX / 2 + 1;
+        // Levels in separate blocks, so only the readers' own sums overflow
+        let ob = book_from(
+            &[(100, HALF), (1_000, HALF)],
+            &[(2_000, HALF), (3_000, HALF)],
+        );
+        assert_eq!(ob.get_depth_until(Side::Bid, 0), Quantity::MAX);
+        assert_eq!(ob.quantity_in_band(Side::Ask, 0, 5_000), Quantity::MAX);
+        assert_eq!(ob.liquidity_ahead(99, Side::Bid), Quantity::MAX);
+        assert_eq!(ob.liquidity_ahead(500, Side::Bid), HALF);
+        assert_eq!(
+            ob.estimated_queue_position(100, Side::Bid, HALF),
+            Quantity::MAX
+        );
+        assert_eq!(ob.vwap(Side::Bid, 2), Some(550.0));
+        assert_eq!(ob.deep_microprice(2), Some(1_525.0));
+        assert_eq!(ob.price_at_depth_percentile(Side::Bid, 0.5), Some(1_000));
+        assert_eq!(
+            ob.log_depth_samples(Side::Bid, 11).last(),
+            Some(&(1_000 - 1_024, Quantity::MAX))
+        );
+        assert_eq!(
+            ob.max_quantity_within_slippage(Side::Ask, 1e9),
+            Quantity::MAX
+        );
+
+        let one = book_from(&[(100, HALF)], &[]);
+        let mut overlay = OverlayBook::new(&one);
+        overlay.set_implied(100, HALF, Side::Bid);
+        overlay.set_implied(90, HALF, Side::Bid);
+        assert_eq!(overlay.get_quantity_at(100, Side::Bid), Some(Quantity::MAX));
+        assert_eq!(
+            overlay.get_top_levels(Side::Bid, 2),
+            vec![(100, Quantity::MAX), (90, HALF)]
+        );
+        assert_eq!(overlay.get_total_quantity(Side::Bid), Quantity::MAX);
     }
 }
@@ -0,0 +1,66 @@
+use crate::interfaces::{OrderBook, Update};
+use std::time::{Duration, Instant};
+
+// ============================================================================
+// SAMPLING MIRROR – FULL-FIDELITY BOOK, THROTTLED DOWNSTREAM
+// ============================================================================
+
+/// Wraps a book so that every update is applied locally, while the sink only
+/// sees the book every `every` updates and/or once per `interval`
+/// The inner book never skips an update; only the emission is throttled
+pub struct SamplingMirror<T, S> {
+    book: T,
+    sink: S,
+    every: u64,
+    interval: Option<Duration>,
+    since_emit: u64,
+    last_emit: Instant,
+}
+
+impl<T: OrderBook, S: FnMut(&T)> SamplingMirror<T, S> {
+    /// `every` = 0 disables the count trigger, `interval` = None disables the time trigger
+    pub fn new(book: T, every: u64, interval: Option<Duration>, sink: S) -> Self {
+        SamplingMirror {
+            book,
+            sink,
+            every,
+            interval,
+            since_emit: 0,
+            last_emit: Instant::now(),
+        }
+    }
+
+    /// Apply the update to the inner book; returns true if the sink was called
+    #[inline]
+    pub fn apply_update(&mut self, update: Update) -> bool {
+        self.book.apply_update(update);
+        self.since_emit += 1;
+
+        let count_due = self.every != 0 && self.since_emit >= self.every;
+        let time_due = self
+            .interval
+            .is_some_and(|interval| self.last_emit.elapsed() >= interval);
+
+        if count_due || time_due {
+            self.flush();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Emit the current state immediately and restart both triggers
+    pub fn flush(&mut self) {
+        (self.sink)(&self.book);
+        self.since_emit = 0;
+        self.last_emit = Instant::now();
+    }
+
+    pub fn book(&self) -> &T {
+        &self.book
+    }
+
+    pub fn into_inner(self) -> T {
+        self.book
+    }
+}
@@ -1,56 +1,288 @@
 // Price-indexed arrays + bitset for fast scanning
 
-use crate::interfaces::{OrderBook, Price, Quantity, Side, Update};
+use crate::interfaces::{
+    OrderBook, OrderBookError, Price, Quantity, QuantityLike, Side, Update, fill_from_levels,
+    imbalance_of, microprice_of,
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
 
-const MAX_PRICE: usize = 200_001;
+// Price range of `OrderBook::new`; see `with_capacity` for others
+const DEFAULT_MAX_PRICE: usize = 200_001;
 const BLOCK_SIZE: usize = 64;
-const NUM_BLOCKS: usize = (MAX_PRICE + BLOCK_SIZE - 1) / BLOCK_SIZE;
+const CHANGE_LOG_CAPACITY: usize = 4096;
+const REORDER_WINDOW: u64 = 64;
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+const SNAPSHOT_MAGIC: &[u8; 8] = b"OBSNAP02";
+// Version 1 had no range words and always used the default layout
+const SNAPSHOT_MAGIC_V1: &[u8; 8] = b"OBSNAP01";
+const SNAPSHOT_INVERTED: u64 = 1;
+// Largest range a snapshot may declare: far above any instrument's tick
+// range, and it bounds what a corrupted header can make `from_snapshot` allocate
+const SNAPSHOT_MAX_PRICE: u64 = 1 << 28;
 
-pub struct OrderBookImpl {
-    // Price-indexed arrays: bids[price] = quantity (0 if empty)
-    bids: Vec<Quantity>,
-    asks: Vec<Quantity>,
+// Bitmask blocks needed to cover prices [0, max_price)
+// Checked so a huge configured range fails loudly instead of wrapping
+const fn blocks_for(max_price: usize) -> usize {
+    match max_price.checked_add(BLOCK_SIZE - 1) {
+        Some(padded) => padded / BLOCK_SIZE,
+        None => panic!("max price overflows the bitmask block count"),
+    }
+}
+
+// The highest indexable price must land in the last block
+const _: () = assert!(
+    DEFAULT_MAX_PRICE > 0
+        && (DEFAULT_MAX_PRICE - 1) / BLOCK_SIZE == blocks_for(DEFAULT_MAX_PRICE) - 1
+);
+
+/// Order book over any `QuantityLike` quantity; `OrderBookImpl` alone is the
+/// u64 book implementing `OrderBook`. Other instantiations (e.g.
+/// `OrderBookImpl<f64>`) share the storage, update path and core reads, while
+/// the sequencing and analytics layers are u64 only
+pub struct OrderBookImpl<Q: QuantityLike = Quantity> {
+    // Price-indexed arrays: bids[slot(price)] = quantity (0 if empty)
+    bids: Vec<Q>,
+    asks: Vec<Q>,
     
-    // Bitsets: one bit per price level, 64 prices per block
+    // Bitsets: one bit per slot, 64 slots per block
     bitmask_bid: Vec<u64>,
     bitmask_ask: Vec<u64>,
+
+    // Total quantity resting in each 64-slot block, for banded sums
+    block_sum_bid: Vec<Q>,
+    block_sum_ask: Vec<Q>,
     
+    // slot(price) = (price ^ slot_flip) + slot_offset: the price itself, or
+    // max_price - 1 - price in an inverted book (flip -1, offset max_price)
+    slot_flip: i64,
+    slot_offset: i64,
+
     // Cached best prices (-1 if empty)
     best_bid: i64,
     best_ask: i64,
+
+    // Cached quantities at the best prices (0 if empty)
+    best_bid_qty: Q,
+    best_ask_qty: Q,
     
     // Cached total quantities
-    total_bid_quantity: Quantity,
-    total_ask_quantity: Quantity,
+    total_bid_quantity: Q,
+    total_ask_quantity: Q,
+
+    // Cached sum of price * quantity per side
+    total_bid_notional: Q::Notional,
+    total_ask_notional: Q::Notional,
+
+    // Number of populated price levels per side
+    bid_level_count: usize,
+    ask_level_count: usize,
+
+    // Last applied sequence number (0 until the first resync)
+    seq: u64,
+
+    // Recent (seq, price, side) changes applied through apply_update_seq
+    change_log: VecDeque<(u64, Price, Side)>,
+    // Changes at or below this sequence are no longer in the log
+    change_log_floor: u64,
+
+    // Updates that arrived ahead of the next expected sequence
+    reorder_buffer: BTreeMap<u64, Update>,
+
+    // Number of internal inconsistencies detected and repaired
+    anomalies: u64,
+
+    // Externally supplied fair/reference price, if any
+    reference_price: Option<Price>,
+
+    // Opt-in count of removals at the current best (touch flicker)
+    track_flicker: bool,
+    touch_flicker: u64,
+
+    // Opt-in last-update time (ns) per price, empty unless timestamp tracking is on
+    level_time_bid: Vec<u64>,
+    level_time_ask: Vec<u64>,
+
+    // Opt-in (time ns, spread) samples, oldest first; capacity 0 = off
+    spread_history: VecDeque<(u64, Option<Price>)>,
+    spread_history_capacity: usize,
+
+    // Opt-in midpoint samples, oldest first; capacity 0 = off
+    mid_history: VecDeque<f64>,
+    mid_history_capacity: usize,
+
+    // Trading halt: while set, apply_update holds or drops updates per halt_policy
+    halted: bool,
+    halt_policy: HaltPolicy,
+    // halted, recent-level tracking on, or a stale sticky best: set/remove
+    // take the cold `divert` path first, so the common case pays one branch
+    diverted: bool,
+    // Held (price, quantity, side) writes; a zero quantity is a removal
+    halt_buffer: Vec<(Price, Q, Side)>,
+
+    // Venue convention `checksum` follows
+    checksum_format: ChecksumFormat,
+
+    // Opt-in ring of the last touched (price, side) levels for prefetch,
+    // next write at recent_next; capacity 0 = off
+    recent_touched: Vec<(Price, Side)>,
+    recent_capacity: usize,
+    recent_next: usize,
+
+    // Opt-in sticky best: removing a best level leaves its price cached with
+    // quantity 0, marked stale, for up to sticky_limit further updates or until
+    // refresh_best; stale_updates counts them. sticky_limit 0 = off
+    sticky_limit: u32,
+    stale_bid: bool,
+    stale_ask: bool,
+    stale_updates: u32,
 }
 
-impl OrderBookImpl {
+// How many updates ahead `apply_batch` prefetches level slots
+const PREFETCH_DISTANCE: usize = 8;
+
+/// What `apply_update` does with updates that arrive while the book is halted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaltPolicy {
+    /// Hold updates and apply them in arrival order on `resume`
+    #[default]
+    Buffer,
+    /// Drop updates
+    Reject,
+}
+
+/// Layout of the level string a venue CRC32s for its book checksum
+/// Prices and quantities are written as plain decimal integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumFormat {
+    /// Top `depth` levels interleaved per depth index, bid before ask, as
+    /// "bid_px:bid_qty:ask_px:ask_qty:...". A side with fewer levels is
+    /// skipped at the deeper indices
+    #[default]
+    Interleaved,
+    /// Top `depth` asks then top `depth` bids, each level's price digits
+    /// followed by its quantity digits, with no separators
+    Concatenated,
+}
+
+// CRC-32 (IEEE 802.3, reflected, polynomial 0xEDB88320) lookup table
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+// `OrderBookImpl::fingerprint` of bid then ask levels, each best first
+fn fingerprint_levels<I: Iterator<Item = (Price, Quantity)>>(sides: [I; 2]) -> u64 {
+    #[inline(always)]
+    fn mix(hash: u64, word: u64) -> u64 {
+        (hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED)
+    }
+
+    let mut hash = 0;
+    for (side, levels) in [Side::Bid, Side::Ask].into_iter().zip(sides) {
+        hash = mix(hash, side as u64);
+        for (price, qty) in levels {
+            hash = mix(hash, price as u64);
+            hash = mix(hash, qty);
+        }
+    }
+
+    // Final avalanche so low bits depend on the whole state
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash
+}
+
+impl<Q: QuantityLike> OrderBookImpl<Q> {
+    // Array index of a price. Branch-free, and its own inverse: see `price_of`
+    #[inline(always)]
+    fn slot(&self, price: Price) -> usize {
+        ((price ^ self.slot_flip) + self.slot_offset) as usize
+    }
+
+    #[inline(always)]
+    fn price_of(&self, slot: usize) -> Price {
+        (slot as Price ^ self.slot_flip) + self.slot_offset
+    }
+
+    // Bitmask blocks covering the price range
+    #[inline(always)]
+    fn num_blocks(&self) -> usize {
+        self.bitmask_bid.len()
+    }
+
+    // Whether a price has a slot at all. A single unsigned compare: negative
+    // prices wrap to values past any range
+    #[inline(always)]
+    fn in_range(&self, price: Price) -> bool {
+        (price as u64) < self.max_price() as u64
+    }
+
+    // Whether a side's best price sits at its highest populated slot, i.e.
+    // scans toward better prices walk the bitmask downward
+    #[inline(always)]
+    fn best_at_top(&self, side: Side) -> bool {
+        (side == Side::Bid) != self.is_inverted()
+    }
+
     #[inline(always)]
-    fn get_bid(&self, price: Price) -> Quantity {
-        unsafe { *self.bids.get_unchecked(price as usize) }
+    fn get_bid(&self, price: Price) -> Q {
+        unsafe { *self.bids.get_unchecked(self.slot(price)) }
     }
     
     #[inline(always)]
-    fn get_ask(&self, price: Price) -> Quantity {
-        unsafe { *self.asks.get_unchecked(price as usize) }
+    fn get_ask(&self, price: Price) -> Q {
+        unsafe { *self.asks.get_unchecked(self.slot(price)) }
     }
     
     #[inline(always)]
-    fn set_bid(&mut self, price: Price, qty: Quantity) {
-        unsafe { *self.bids.get_unchecked_mut(price as usize) = qty; }
+    fn set_bid(&mut self, price: Price, qty: Q) {
+        let slot = self.slot(price);
+        unsafe {
+            *self.bids.get_unchecked_mut(slot) = qty;
+        }
     }
     
     #[inline(always)]
-    fn set_ask(&mut self, price: Price, qty: Quantity) {
-        unsafe { *self.asks.get_unchecked_mut(price as usize) = qty; }
+    fn set_ask(&mut self, price: Price, qty: Q) {
+        let slot = self.slot(price);
+        unsafe {
+            *self.asks.get_unchecked_mut(slot) = qty;
+        }
     }
     
     #[inline(always)]
     fn update_bitmask_bid(&mut self, price: Price, has_qty: bool) {
-        let price_usize = price as usize;
-        let block = price_usize / BLOCK_SIZE;
-        let bit = price_usize % BLOCK_SIZE;
+        let slot = self.slot(price);
+        let block = slot / BLOCK_SIZE;
+        let bit = slot % BLOCK_SIZE;
         let mask = 1u64 << bit;
+        debug_assert!(
+            block < self.bitmask_bid.len(),
+            "price {price} maps past the last bitmask block"
+        );
         
         if has_qty {
             unsafe { *self.bitmask_bid.get_unchecked_mut(block) |= mask; }
@@ -61,10 +293,14 @@ impl OrderBookImpl {
     
     #[inline(always)]
     fn update_bitmask_ask(&mut self, price: Price, has_qty: bool) {
-        let price_usize = price as usize;
-        let block = price_usize / BLOCK_SIZE;
-        let bit = price_usize % BLOCK_SIZE;
+        let slot = self.slot(price);
+        let block = slot / BLOCK_SIZE;
+        let bit = slot % BLOCK_SIZE;
         let mask = 1u64 << bit;
+        debug_assert!(
+            block < self.bitmask_ask.len(),
+            "price {price} maps past the last bitmask block"
+        );
         
         if has_qty {
             unsafe { *self.bitmask_ask.get_unchecked_mut(block) |= mask; }
@@ -73,70 +309,376 @@ impl OrderBookImpl {
         }
     }
     
+    // Every in-range price must map to an existing bitmask block and block sum,
+    // since the update path indexes them unchecked
+    fn assert_layout(&self) {
+        let max_price = self.bids.len();
+        let blocks = blocks_for(max_price);
+        assert_eq!(self.asks.len(), max_price);
+        assert_eq!(self.bitmask_bid.len(), blocks);
+        assert_eq!(self.bitmask_ask.len(), blocks);
+        assert_eq!(self.block_sum_bid.len(), blocks);
+        assert_eq!(self.block_sum_ask.len(), blocks);
+        assert!(max_price == 0 || (max_price - 1) / BLOCK_SIZE < blocks);
+    }
+
+    // Set a nonzero quantity at a price, returning the previous quantity
     #[inline(always)]
-    fn recompute_best_bid(&mut self) {
-        let start_block = ((self.best_bid.max(0) as usize) / BLOCK_SIZE).min(NUM_BLOCKS - 1);
-        let mut block = start_block;
-        
-        let mask = unsafe { *self.bitmask_bid.get_unchecked(block) };
-        if mask != 0 {
-            let bit = 63 - mask.leading_zeros() as usize;
-            self.best_bid = (block * BLOCK_SIZE + bit) as i64;
-            return;
+    fn upsert_bid(&mut self, price: Price, quantity: Q) -> Q {
+        let old_qty = self.get_bid(price);
+        // Feeds often resend unchanged levels; skip the writes entirely
+        if old_qty == quantity {
+            return old_qty;
         }
-        
-        while block > 0 {
-            block -= 1;
-            let mask = unsafe { *self.bitmask_bid.get_unchecked(block) };
-            if mask != 0 {
-                let bit = 63 - mask.leading_zeros() as usize;
-                self.best_bid = (block * BLOCK_SIZE + bit) as i64;
-                return;
+        if old_qty.is_zero() {
+            self.insert_bid(price, quantity);
+            return old_qty;
+        }
+
+        // Hot path: resize a resting level. It can't become a new best, only
+        // refresh the cached quantity if it already is one
+        self.set_bid(price, quantity);
+        self.total_bid_quantity = self
+            .total_bid_quantity
+            .wrapping_sub(old_qty)
+            .wrapping_add(quantity);
+        self.total_bid_notional =
+            self.total_bid_notional - old_qty.notional(price) + quantity.notional(price);
+        let block = self.slot(price) / BLOCK_SIZE;
+        unsafe {
+            let sum = self.block_sum_bid.get_unchecked_mut(block);
+            *sum = sum.wrapping_sub(old_qty).wrapping_add(quantity);
+        }
+        if price == self.best_bid {
+            self.best_bid_qty = quantity;
+        }
+        old_qty
+    }
+
+    // New level at an empty price, kept off the resize path
+    #[cold]
+    fn insert_bid(&mut self, price: Price, quantity: Q) {
+        self.set_bid(price, quantity);
+        self.update_bitmask_bid(price, true);
+        self.bid_level_count += 1;
+
+        self.total_bid_quantity = self.total_bid_quantity.wrapping_add(quantity);
+        self.total_bid_notional = self.total_bid_notional + quantity.notional(price);
+        let block = self.slot(price) / BLOCK_SIZE;
+        unsafe {
+            let sum = self.block_sum_bid.get_unchecked_mut(block);
+            *sum = sum.wrapping_add(quantity);
+        }
+
+        if price >= self.best_bid {
+            self.best_bid = price;
+            self.best_bid_qty = quantity;
+            if self.best_ask >= 0 && price > self.best_ask {
+                self.uncross(Side::Bid, price);
             }
         }
-        
-        self.best_bid = -1;
     }
-    
+
     #[inline(always)]
-    fn recompute_best_ask(&mut self) {
-        let start_block = ((self.best_ask.max(0) as usize) / BLOCK_SIZE).min(NUM_BLOCKS - 1);
-        let mut block = start_block;
-        
-        let mask = unsafe { *self.bitmask_ask.get_unchecked(block) };
-        if mask != 0 {
-            let bit = mask.trailing_zeros() as usize;
-            self.best_ask = (block * BLOCK_SIZE + bit) as i64;
-            return;
+    fn upsert_ask(&mut self, price: Price, quantity: Q) -> Q {
+        let old_qty = self.get_ask(price);
+        // Feeds often resend unchanged levels; skip the writes entirely
+        if old_qty == quantity {
+            return old_qty;
         }
-        
-        while block < NUM_BLOCKS - 1 {
-            block += 1;
-            let mask = unsafe { *self.bitmask_ask.get_unchecked(block) };
-            if mask != 0 {
-                let bit = mask.trailing_zeros() as usize;
-                self.best_ask = (block * BLOCK_SIZE + bit) as i64;
+        if old_qty.is_zero() {
+            self.insert_ask(price, quantity);
+            return old_qty;
+        }
+
+        // Hot path: resize a resting level. It can't become a new best, only
+        // refresh the cached quantity if it already is one
+        self.set_ask(price, quantity);
+        self.total_ask_quantity = self
+            .total_ask_quantity
+            .wrapping_sub(old_qty)
+            .wrapping_add(quantity);
+        self.total_ask_notional =
+            self.total_ask_notional - old_qty.notional(price) + quantity.notional(price);
+        let block = self.slot(price) / BLOCK_SIZE;
+        unsafe {
+            let sum = self.block_sum_ask.get_unchecked_mut(block);
+            *sum = sum.wrapping_sub(old_qty).wrapping_add(quantity);
+        }
+        if price == self.best_ask {
+            self.best_ask_qty = quantity;
+        }
+        old_qty
+    }
+
+    // New level at an empty price, kept off the resize path
+    #[cold]
+    fn insert_ask(&mut self, price: Price, quantity: Q) {
+        self.set_ask(price, quantity);
+        self.update_bitmask_ask(price, true);
+        self.ask_level_count += 1;
+
+        self.total_ask_quantity = self.total_ask_quantity.wrapping_add(quantity);
+        self.total_ask_notional = self.total_ask_notional + quantity.notional(price);
+        let block = self.slot(price) / BLOCK_SIZE;
+        unsafe {
+            let sum = self.block_sum_ask.get_unchecked_mut(block);
+            *sum = sum.wrapping_add(quantity);
+        }
+
+        if self.best_ask < 0 || price <= self.best_ask {
+            self.best_ask = price;
+            self.best_ask_qty = quantity;
+            if price < self.best_bid {
+                self.uncross(Side::Ask, price);
+            }
+        }
+    }
+
+    // A new best on `side` at `price` crossed the opposite touch. The newer
+    // level wins: clear every opposite level priced through it and rescan that
+    // side's best (dropping a stale sticky best, which may be what crossed)
+    #[cold]
+    fn uncross(&mut self, side: Side, price: Price) {
+        match side {
+            Side::Bid => {
+                self.stale_ask = false;
+                while self.best_ask >= 0 && self.best_ask < price {
+                    self.clear_ask(self.best_ask);
+                    self.recompute_best_ask();
+                }
+            }
+            Side::Ask => {
+                self.stale_bid = false;
+                while self.best_bid > price {
+                    self.clear_bid(self.best_bid);
+                    self.recompute_best_bid();
+                }
+            }
+        }
+        self.update_diverted();
+    }
+
+    // Clear a price level if present, returning the removed quantity
+    #[inline(always)]
+    fn remove_bid(&mut self, price: Price) -> Q {
+        let old_qty = self.clear_bid(price);
+        if !old_qty.is_zero() && price == self.best_bid {
+            if self.track_flicker {
+                self.touch_flicker += 1;
+            }
+            if self.sticky_limit != 0 {
+                self.defer_best(Side::Bid);
+            } else {
+                self.recompute_best_bid();
+            }
+        }
+        old_qty
+    }
+
+    // `remove_*` without maintaining the cached best, for batched removals
+    #[inline(always)]
+    fn clear_bid(&mut self, price: Price) -> Q {
+        let old_qty = self.get_bid(price);
+        if !old_qty.is_zero() {
+            self.set_bid(price, Q::ZERO);
+            self.update_bitmask_bid(price, false);
+            self.total_bid_quantity = self.total_bid_quantity.wrapping_sub(old_qty);
+            self.total_bid_notional = self.total_bid_notional - old_qty.notional(price);
+            self.bid_level_count -= 1;
+            let block = self.slot(price) / BLOCK_SIZE;
+            unsafe {
+                let sum = self.block_sum_bid.get_unchecked_mut(block);
+                *sum = sum.wrapping_sub(old_qty);
+            }
+        }
+        old_qty
+    }
+
+    #[inline(always)]
+    fn remove_ask(&mut self, price: Price) -> Q {
+        let old_qty = self.clear_ask(price);
+        if !old_qty.is_zero() && price == self.best_ask {
+            if self.track_flicker {
+                self.touch_flicker += 1;
+            }
+            if self.sticky_limit != 0 {
+                self.defer_best(Side::Ask);
+            } else {
+                self.recompute_best_ask();
+            }
+        }
+        old_qty
+    }
+
+    #[inline(always)]
+    fn clear_ask(&mut self, price: Price) -> Q {
+        let old_qty = self.get_ask(price);
+        if !old_qty.is_zero() {
+            self.set_ask(price, Q::ZERO);
+            self.update_bitmask_ask(price, false);
+            self.total_ask_quantity = self.total_ask_quantity.wrapping_sub(old_qty);
+            self.total_ask_notional = self.total_ask_notional - old_qty.notional(price);
+            self.ask_level_count -= 1;
+            let block = self.slot(price) / BLOCK_SIZE;
+            unsafe {
+                let sum = self.block_sum_ask.get_unchecked_mut(block);
+                *sum = sum.wrapping_sub(old_qty);
+            }
+        }
+        old_qty
+    }
+
+    #[inline(always)]
+    fn bitmask(&self, side: Side) -> &[u64] {
+        match side {
+            Side::Bid => &self.bitmask_bid,
+            Side::Ask => &self.bitmask_ask,
+        }
+    }
+
+    #[inline(always)]
+    fn block_sums(&self, side: Side) -> &[Q] {
+        match side {
+            Side::Bid => &self.block_sum_bid,
+            Side::Ask => &self.block_sum_ask,
+        }
+    }
+
+    #[inline(always)]
+    fn quantities(&self, side: Side) -> &[Q] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    // Hint the CPU to pull a level's quantity slot into cache. Out-of-range
+    // prices are skipped; a no-op off x86_64
+    #[inline(always)]
+    fn prefetch_level(&self, price: Price, side: Side) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+            // Checked before `slot`, which can overflow for far-off prices
+            if !self.in_range(price) {
                 return;
             }
+            let quantities = self.quantities(side);
+            // SAFETY: in range, so slot is in bounds; prefetch never faults anyway
+            unsafe {
+                _mm_prefetch(
+                    quantities.as_ptr().add(self.slot(price)).cast::<i8>(),
+                    _MM_HINT_T0,
+                )
+            };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = (price, side);
+    }
+
+    fn note_touched(&mut self, price: Price, side: Side) {
+        if self.recent_touched.len() < self.recent_capacity {
+            self.recent_touched.push((price, side));
+        } else {
+            self.recent_touched[self.recent_next] = (price, side);
+        }
+        self.recent_next = (self.recent_next + 1) % self.recent_capacity;
+    }
+
+    // Clamp [low, high] to the index range, or None if they don't intersect
+    #[inline(always)]
+    fn clamp_band(&self, low: Price, high: Price) -> Option<(usize, usize)> {
+        let low = low.max(0);
+        let high = high.min(self.max_price() - 1);
+        if low > high {
+            None
+        } else {
+            Some((low as usize, high as usize))
+        }
+    }
+
+    // Clamped price band [low, high] as the inclusive slot range covering it
+    #[inline(always)]
+    fn slot_band(&self, low: Price, high: Price) -> Option<(usize, usize)> {
+        let (low, high) = self.clamp_band(low, high)?;
+        let (a, b) = (self.slot(low as Price), self.slot(high as Price));
+        Some((a.min(b), a.max(b)))
+    }
+
+    // Bits of `block` whose slots fall inside [low, high]
+    #[inline(always)]
+    fn band_mask(block: usize, low: usize, high: usize) -> u64 {
+        let mut mask = !0u64;
+        if block == low / BLOCK_SIZE {
+            mask &= !0u64 << (low % BLOCK_SIZE);
+        }
+        if block == high / BLOCK_SIZE {
+            mask &= !0u64 >> (BLOCK_SIZE - 1 - high % BLOCK_SIZE);
+        }
+        mask
+    }
+
+    // Best populated price on a side, scanning from the block holding `from`
+    // (clamped to 0) toward worse prices; -1 if nothing is found
+    #[inline(always)]
+    fn scan_best(&self, side: Side, from: Price) -> Price {
+        let bitmask = self.bitmask(side);
+        let mut block = (self.slot(from.max(0)) / BLOCK_SIZE).min(self.num_blocks() - 1);
+
+        if self.best_at_top(side) {
+            loop {
+                let mask = unsafe { *bitmask.get_unchecked(block) };
+                if mask != 0 {
+                    let bit = 63 - mask.leading_zeros() as usize;
+                    return self.price_of(block * BLOCK_SIZE + bit);
+                }
+                if block == 0 {
+                    return -1;
+                }
+                block -= 1;
+            }
+        } else {
+            loop {
+                let mask = unsafe { *bitmask.get_unchecked(block) };
+                if mask != 0 {
+                    let bit = mask.trailing_zeros() as usize;
+                    return self.price_of(block * BLOCK_SIZE + bit);
+                }
+                if block >= self.num_blocks() - 1 {
+                    return -1;
+                }
+                block += 1;
+            }
         }
-        
-        self.best_ask = -1;
+    }
+
+    #[inline(always)]
+    fn recompute_best_bid(&mut self) {
+        self.best_bid = self.scan_best(Side::Bid, self.best_bid);
+        self.best_bid_qty = if self.best_bid >= 0 {
+            self.get_bid(self.best_bid)
+        } else {
+            Q::ZERO
+        };
+    }
+
+    #[inline(always)]
+    fn recompute_best_ask(&mut self) {
+        self.best_ask = self.scan_best(Side::Ask, self.best_ask);
+        self.best_ask_qty = if self.best_ask >= 0 {
+            self.get_ask(self.best_ask)
+        } else {
+            Q::ZERO
+        };
     }
 }
 
 impl OrderBook for OrderBookImpl {
+    /// Default-range book, allocated as described on `with_capacity`
     #[inline]
     fn new() -> Self {
-        OrderBookImpl {
-            bids: vec![0; MAX_PRICE],
-            asks: vec![0; MAX_PRICE],
-            bitmask_bid: vec![0; NUM_BLOCKS],
-            bitmask_ask: vec![0; NUM_BLOCKS],
-            best_bid: -1,
-            best_ask: -1,
-            total_bid_quantity: 0,
-            total_ask_quantity: 0,
-        }
+        Self::empty()
     }
 
     #[inline(always)]
@@ -146,208 +688,3081 @@ impl OrderBook for OrderBookImpl {
                 price,
                 quantity,
                 side,
-            } => {
-                if quantity == 0 {
-                    match side {
-                        Side::Bid => {
-                            let old_qty = self.get_bid(price);
-                            if old_qty > 0 {
-                                self.set_bid(price, 0);
-                                self.update_bitmask_bid(price, false);
-                                self.total_bid_quantity -= old_qty;
-                                
-                                if price == self.best_bid {
-                                    self.recompute_best_bid();
-                                }
-                            }
-                        }
-                        Side::Ask => {
-                            let old_qty = self.get_ask(price);
-                            if old_qty > 0 {
-                                self.set_ask(price, 0);
-                                self.update_bitmask_ask(price, false);
-                                self.total_ask_quantity -= old_qty;
-                                
-                                if price == self.best_ask {
-                                    self.recompute_best_ask();
-                                }
-                            }
-                        }
-                    }
-                    return;
-                }
-                
-                match side {
-                    Side::Bid => {
-                        let old_qty = self.get_bid(price);
-                        let was_new = old_qty == 0;
-                        
-                        self.set_bid(price, quantity);
-                        
-                        if was_new {
-                            self.update_bitmask_bid(price, true);
-                        }
-                        
-                        let diff = quantity as i64 - old_qty as i64;
-                        self.total_bid_quantity = (self.total_bid_quantity as i64 + diff) as u64;
-                        
-                        self.best_bid = self.best_bid.max(price);
-                    }
-                    Side::Ask => {
-                        let old_qty = self.get_ask(price);
-                        let was_new = old_qty == 0;
-                        
-                        self.set_ask(price, quantity);
-                        
-                        if was_new {
-                            self.update_bitmask_ask(price, true);
-                        }
-                        
-                        let diff = quantity as i64 - old_qty as i64;
-                        self.total_ask_quantity = (self.total_ask_quantity as i64 + diff) as u64;
-                        
-                        if self.best_ask < 0 {
-                            self.best_ask = price;
-                        } else {
-                            self.best_ask = self.best_ask.min(price);
-                        }
-                    }
-                }
-            }
-            Update::Remove { price, side } => {
-                match side {
-                    Side::Bid => {
-                        let old_qty = self.get_bid(price);
-                        if old_qty > 0 {
-                            self.set_bid(price, 0);
-                            self.update_bitmask_bid(price, false);
-                            self.total_bid_quantity -= old_qty;
-                            
-                            if price == self.best_bid {
-                                self.recompute_best_bid();
-                            }
-                        }
-                    }
-                    Side::Ask => {
-                        let old_qty = self.get_ask(price);
-                        if old_qty > 0 {
-                            self.set_ask(price, 0);
-                            self.update_bitmask_ask(price, false);
-                            self.total_ask_quantity -= old_qty;
-                            
-                            if price == self.best_ask {
-                                self.recompute_best_ask();
-                            }
-                        }
-                    }
-                }
-            }
+            } => self.set(price, quantity, side),
+            Update::Remove { price, side } => self.remove(price, side),
         }
     }
 
+    // The methods below resolve to the inherent methods shared by every
+    // quantity type, not back to this impl
+
+    #[inline(always)]
+    fn set(&mut self, price: Price, quantity: Quantity, side: Side) {
+        OrderBookImpl::set(self, price, quantity, side)
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, price: Price, side: Side) {
+        OrderBookImpl::remove(self, price, side)
+    }
+
     #[inline(always)]
     fn get_spread(&self) -> Option<Price> {
-        let bid = self.best_bid;
-        let ask = self.best_ask;
-        if bid >= 0 && ask >= 0 {
-            Some(ask - bid)
-        } else {
-            None
-        }
+        OrderBookImpl::get_spread(self)
     }
 
     #[inline(always)]
     fn get_best_bid(&self) -> Option<Price> {
-        let bid = self.best_bid;
-        if bid >= 0 {
-            Some(bid)
-        } else {
-            None
-        }
+        OrderBookImpl::get_best_bid(self)
     }
 
     #[inline(always)]
     fn get_best_ask(&self) -> Option<Price> {
-        let ask = self.best_ask;
-        if ask >= 0 {
-            Some(ask)
-        } else {
-            None
-        }
+        OrderBookImpl::get_best_ask(self)
     }
 
     #[inline(always)]
     fn get_quantity_at(&self, price: Price, side: Side) -> Option<Quantity> {
-        match side {
-            Side::Bid => {
-                let qty = self.get_bid(price);
-                if qty > 0 {
-                    Some(qty)
-                } else {
-                    None
-                }
-            }
-            Side::Ask => {
-                let qty = self.get_ask(price);
-                if qty > 0 {
-                    Some(qty)
-                } else {
-                    None
-                }
-            }
-        }
+        OrderBookImpl::get_quantity_at(self, price, side)
     }
 
     fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
-        match side {
-            Side::Bid => {
-                let mut result = Vec::with_capacity(n);
-                if self.best_bid < 0 {
-                    return result;
-                }
-                
-                let mut count = 0;
-                let mut p = self.best_bid;
-                
-                while p >= 0 && count < n {
-                    let qty = self.get_bid(p);
-                    if qty > 0 {
-                        result.push((p, qty));
-                        count += 1;
-                    }
-                    p -= 1;
-                }
-                
-                result
-            }
-            Side::Ask => {
-                let mut result = Vec::with_capacity(n);
-                if self.best_ask < 0 {
-                    return result;
-                }
-                
-                let mut count = 0;
-                let mut p = self.best_ask;
-                
-                while p < MAX_PRICE as i64 && count < n {
-                    let qty = self.get_ask(p);
-                    if qty > 0 {
-                        result.push((p, qty));
-                        count += 1;
-                    }
-                    p += 1;
-                }
-                
-                result
-            }
-        }
+        OrderBookImpl::get_top_levels(self, side, n)
     }
 
     #[inline(always)]
     fn get_total_quantity(&self, side: Side) -> Quantity {
-        match side {
-            Side::Bid => self.total_bid_quantity,
-            Side::Ask => self.total_ask_quantity,
+        OrderBookImpl::get_total_quantity(self, side)
+    }
+
+    #[inline]
+    fn liquidity_ahead(&self, price: Price, side: Side) -> Quantity {
+        OrderBookImpl::liquidity_ahead(self, price, side)
+    }
+
+    fn fill_cost(&self, side: Side, quantity: Quantity) -> Option<(Quantity, u64)> {
+        OrderBookImpl::fill_cost(self, side, quantity)
+    }
+
+    #[inline]
+    fn imbalance(&self, depth: usize) -> Option<f64> {
+        OrderBookImpl::imbalance(self, depth)
+    }
+
+    #[inline(always)]
+    fn mid_price(&self) -> Option<Price> {
+        OrderBookImpl::mid_price(self)
+    }
+
+    #[inline(always)]
+    fn microprice(&self) -> Option<f64> {
+        OrderBookImpl::microprice(self)
+    }
+}
+
+/// A price proven to lie inside a book's index range when it was built
+/// Applying it still goes through `apply_update`, whose range check is a
+/// single compare: books can have different ranges, so a price validated
+/// against one may be out of range for another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatedPrice(Price);
+
+impl ValidatedPrice {
+    #[inline]
+    pub fn new<Q: QuantityLike>(price: Price, book: &OrderBookImpl<Q>) -> Option<ValidatedPrice> {
+        book.in_range(price).then_some(ValidatedPrice(price))
+    }
+
+    #[inline(always)]
+    pub fn get(self) -> Price {
+        self.0
+    }
+}
+
+/// Update whose price has already been validated against the book
+#[derive(Debug, Clone)]
+pub enum ValidatedUpdate {
+    Set {
+        price: ValidatedPrice,
+        quantity: Quantity,
+        side: Side,
+    },
+    Remove {
+        price: ValidatedPrice,
+        side: Side,
+    },
+}
+
+impl From<ValidatedUpdate> for Update {
+    #[inline(always)]
+    fn from(update: ValidatedUpdate) -> Update {
+        match update {
+            ValidatedUpdate::Set {
+                price,
+                quantity,
+                side,
+            } => Update::Set {
+                price: price.get(),
+                quantity,
+                side,
+            },
+            ValidatedUpdate::Remove { price, side } => Update::Remove {
+                price: price.get(),
+                side,
+            },
+        }
+    }
+}
+
+/// Split a recorded stream into the updates safe to apply, in order, and the
+/// (index, error) of each one dropped. A price is dropped unless
+/// `range.0 <= price <= range.1` (both ends inclusive); a Set is dropped
+/// if applying the kept updates to an empty book would take its side's total
+/// past `Quantity::MAX`
+pub fn sanitize_stream(
+    updates: &[Update],
+    range: (Price, Price),
+) -> (Vec<Update>, Vec<(usize, OrderBookError)>) {
+    let (low, high) = range;
+    let mut kept = Vec::with_capacity(updates.len());
+    let mut rejected = Vec::new();
+    // Resting quantity per level and total per side after the kept updates
+    let mut levels: HashMap<(Price, Side), Quantity> = HashMap::new();
+    let mut totals: [Quantity; 2] = [0, 0];
+
+    for (index, update) in updates.iter().enumerate() {
+        let (Update::Set { price, side, .. } | Update::Remove { price, side }) = *update;
+        if !(low..=high).contains(&price) {
+            rejected.push((index, OrderBookError::PriceOutOfRange { price, low, high }));
+            continue;
+        }
+        let quantity = match *update {
+            Update::Set { quantity, .. } => quantity,
+            Update::Remove { .. } => 0,
+        };
+        let total = &mut totals[side as usize];
+        let previous = levels.get(&(price, side)).copied().unwrap_or(0);
+        let Some(new_total) = (*total - previous).checked_add(quantity) else {
+            rejected.push((index, OrderBookError::QuantityOverflow { quantity, side }));
+            continue;
+        };
+        *total = new_total;
+        if quantity == 0 {
+            levels.remove(&(price, side));
+        } else {
+            levels.insert((price, side), quantity);
+        }
+        kept.push(update.clone());
+    }
+    (kept, rejected)
+}
+
+/// Whether `q` converts to f64 exactly. Every quantity up to 2^53 does;
+/// above that only those that happen to land on a representable value
+#[inline(always)]
+pub fn quantity_fits_f64(q: Quantity) -> bool {
+    let f = q as f64;
+    // u64::MAX rounds up to 2^64, which `as u64` would saturate back down
+    f < 18_446_744_073_709_551_616.0 && f as Quantity == q
+}
+
+/// `q as f64`, with a debug assertion that the conversion is exact
+/// Used wherever quantities feed float analytics, so large sizes that would
+/// silently round show up in debug builds and tests
+#[inline(always)]
+pub fn quantity_to_f64_checked(q: Quantity) -> f64 {
+    debug_assert!(quantity_fits_f64(q), "quantity {q} loses precision as f64");
+    q as f64
+}
+
+/// `quantity_to_f64_checked` for sums of quantities, which can pass
+/// `Quantity::MAX`
+#[inline(always)]
+pub fn quantity_sum_to_f64_checked(q: u128) -> f64 {
+    let f = q as f64;
+    debug_assert!(f as u128 == q, "quantity {q} loses precision as f64");
+    f
+}
+
+/// Owned (price, quantity) levels of one side, best first
+pub type LevelVec = Vec<(Price, Quantity)>;
+
+/// First and second populated levels on each side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TopTwo {
+    pub bid0: Option<(Price, Quantity)>,
+    pub bid1: Option<(Price, Quantity)>,
+    pub ask0: Option<(Price, Quantity)>,
+    pub ask1: Option<(Price, Quantity)>,
+}
+
+/// Outcome of `execute_market_order`
+/// `filled < requested` means the side ran out before the order was filled
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionReport {
+    /// (price, quantity) taken at each level, best first
+    pub fills: Vec<(Price, Quantity)>,
+    pub filled: Quantity,
+    pub requested: Quantity,
+    /// VWAP of the fills; 0.0 when nothing filled
+    pub avg_price: f64,
+}
+
+/// One row of a depth-of-market ladder, see `display_rows`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayRow {
+    Ask {
+        price: Price,
+        qty: Quantity,
+    },
+    /// Synthetic row between the sides: midpoint and bid-ask spread
+    Mid {
+        price: f64,
+        spread: Price,
+    },
+    Bid {
+        price: Price,
+        qty: Quantity,
+    },
+}
+
+/// Top of book in a fixed C layout, for publishing into shared memory
+/// An empty side reads as price -1 with quantity 0
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BboSnapshot {
+    pub best_bid: i64,
+    pub bid_qty: u64,
+    pub best_ask: i64,
+    pub ask_qty: u64,
+    pub seq: u64,
+}
+
+/// Whole-book state as data: the populated levels of each side, best first,
+/// with the cached totals, sequence number and price range. Only levels are
+/// stored, never the price-indexed arrays, so its size follows the depth.
+/// Serializable with the `serde` feature
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookSnapshot {
+    pub max_price: Price,
+    pub seq: u64,
+    pub bids: Vec<(Price, Quantity)>,
+    pub asks: Vec<(Price, Quantity)>,
+    pub total_bid_quantity: Quantity,
+    pub total_ask_quantity: Quantity,
+}
+
+impl<Q: QuantityLike> OrderBookImpl<Q> {
+    /// Empty book. `OrderBook::new` for the u64 book; use this to build
+    /// other instantiations, e.g. `OrderBookImpl::<f64>::empty()`
+    #[inline]
+    pub fn empty() -> Self {
+        Self::with_inverted_index(false)
+    }
+
+    /// Empty book whose arrays are indexed highest price first when `inverted`
+    /// (index 0 holds the top price), flipping which end of a bitmask block
+    /// holds each side's best. Only the layout changes: every query, including
+    /// best bid = highest price and best ask = lowest, answers as for `empty()`
+    pub fn with_inverted_index(inverted: bool) -> Self {
+        Self::with_layout(DEFAULT_MAX_PRICE, inverted)
+    }
+
+    /// Empty book indexing prices [0, `max_price`) instead of the default
+    /// range: memory is two quantity arrays of `max_price` entries plus a bit
+    /// per price, so narrow instruments can size down and fine-tick ones up.
+    /// The arrays are zeroed allocations (calloc) for integer and f64
+    /// quantities, not alloc + memset: the OS hands back pages that are
+    /// already zero, so construction is a few mmap calls and each 4 KiB page
+    /// is only paid for, as a page fault, the first time it is touched. The
+    /// "Construction" line of the benchmark output measures it
+    /// Panics if `max_price` is 0 or doesn't fit a `Price`
+    pub fn with_capacity(max_price: usize) -> Self {
+        Self::with_layout(max_price, false)
+    }
+
+    fn with_layout(max_price: usize, inverted: bool) -> Self {
+        assert!(
+            max_price > 0 && Price::try_from(max_price).is_ok(),
+            "max price {max_price} outside 1..=Price::MAX"
+        );
+        let blocks = blocks_for(max_price);
+        let book = OrderBookImpl {
+            bids: vec![Q::ZERO; max_price],
+            asks: vec![Q::ZERO; max_price],
+            bitmask_bid: vec![0; blocks],
+            bitmask_ask: vec![0; blocks],
+            block_sum_bid: vec![Q::ZERO; blocks],
+            block_sum_ask: vec![Q::ZERO; blocks],
+            slot_flip: if inverted { -1 } else { 0 },
+            slot_offset: if inverted { max_price as i64 } else { 0 },
+            best_bid: -1,
+            best_ask: -1,
+            best_bid_qty: Q::ZERO,
+            best_ask_qty: Q::ZERO,
+            total_bid_quantity: Q::ZERO,
+            total_ask_quantity: Q::ZERO,
+            total_bid_notional: Q::ZERO_NOTIONAL,
+            total_ask_notional: Q::ZERO_NOTIONAL,
+            bid_level_count: 0,
+            ask_level_count: 0,
+            seq: 0,
+            change_log: VecDeque::with_capacity(CHANGE_LOG_CAPACITY),
+            change_log_floor: 0,
+            reorder_buffer: BTreeMap::new(),
+            anomalies: 0,
+            reference_price: None,
+            track_flicker: false,
+            touch_flicker: 0,
+            level_time_bid: Vec::new(),
+            level_time_ask: Vec::new(),
+            spread_history: VecDeque::new(),
+            spread_history_capacity: 0,
+            mid_history: VecDeque::new(),
+            mid_history_capacity: 0,
+            halted: false,
+            diverted: false,
+            halt_policy: HaltPolicy::Buffer,
+            halt_buffer: Vec::new(),
+            checksum_format: ChecksumFormat::Interleaved,
+            recent_touched: Vec::new(),
+            recent_capacity: 0,
+            recent_next: 0,
+            sticky_limit: 0,
+            stale_bid: false,
+            stale_ask: false,
+            stale_updates: 0,
+        };
+        book.assert_layout();
+        book
+    }
+
+    /// Set a level's quantity; zero removes it. Same as applying `Update::Set`,
+    /// for any quantity type, including holding or dropping it while halted
+    /// A price outside [0, `max_price`) is ignored
+    #[inline(always)]
+    pub fn set(&mut self, price: Price, quantity: Q, side: Side) {
+        if !self.in_range(price) {
+            return;
+        }
+        if self.diverted && self.divert(price, quantity, side) {
+            return;
+        }
+        self.set_level(price, quantity, side);
+    }
+
+    /// Remove a level if present. Same as applying `Update::Remove`
+    #[inline(always)]
+    pub fn remove(&mut self, price: Price, side: Side) {
+        if !self.in_range(price) {
+            return;
+        }
+        if self.diverted && self.divert(price, Q::ZERO, side) {
+            return;
+        }
+        self.remove_level(price, side);
+    }
+
+    /// Clear every listed level, recomputing each side's best at most once
+    /// The final state matches removing the prices one by one; while halted
+    /// each removal is held or dropped like a single `remove`
+    pub fn remove_prices(&mut self, prices: &[(Price, Side)]) {
+        if self.halted {
+            for &(price, side) in prices {
+                self.hold(price, Q::ZERO, side);
+            }
+            return;
+        }
+
+        let mut bid_best_removed = false;
+        let mut ask_best_removed = false;
+        for &(price, side) in prices {
+            if !self.in_range(price) {
+                continue;
+            }
+            match side {
+                Side::Bid => {
+                    if !self.clear_bid(price).is_zero() && price == self.best_bid {
+                        bid_best_removed = true;
+                    }
+                }
+                Side::Ask => {
+                    if !self.clear_ask(price).is_zero() && price == self.best_ask {
+                        ask_best_removed = true;
+                    }
+                }
+            }
+        }
+
+        for (removed, side) in [(bid_best_removed, Side::Bid), (ask_best_removed, Side::Ask)] {
+            if !removed {
+                continue;
+            }
+            if self.track_flicker {
+                self.touch_flicker += 1;
+            }
+            match side {
+                Side::Bid => {
+                    self.stale_bid = false;
+                    self.recompute_best_bid();
+                }
+                Side::Ask => {
+                    self.stale_ask = false;
+                    self.recompute_best_ask();
+                }
+            }
+        }
+    }
+
+    // Off the hot path: hold the update if halted (returning true, so the
+    // caller skips it), else record the level for `prefetch_recent` and
+    // count it against a stale sticky best
+    #[cold]
+    fn divert(&mut self, price: Price, quantity: Q, side: Side) -> bool {
+        if self.halted {
+            self.hold(price, quantity, side);
+            return true;
+        }
+        if self.recent_capacity != 0 {
+            self.note_touched(price, side);
+        }
+        if self.stale_bid || self.stale_ask {
+            if self.stale_updates >= self.sticky_limit {
+                self.refresh_best();
+            } else {
+                self.stale_updates += 1;
+                // A level set at or through a stale best becomes the real best
+                if !quantity.is_zero() {
+                    match side {
+                        Side::Bid if price >= self.best_bid => self.stale_bid = false,
+                        Side::Ask if price <= self.best_ask => self.stale_ask = false,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        self.update_diverted();
+        false
+    }
+
+    #[inline]
+    fn update_diverted(&mut self) {
+        self.diverted =
+            self.halted || self.recent_capacity != 0 || self.stale_bid || self.stale_ask;
+    }
+
+    // Sticky mode: the best level on `side` was just emptied; keep its price
+    // cached as a stale best instead of rescanning
+    #[cold]
+    fn defer_best(&mut self, side: Side) {
+        if !(self.stale_bid || self.stale_ask) {
+            self.stale_updates = 0;
+        }
+        match side {
+            Side::Bid => {
+                self.stale_bid = true;
+                self.best_bid_qty = Q::ZERO;
+            }
+            Side::Ask => {
+                self.stale_ask = true;
+                self.best_ask_qty = Q::ZERO;
+            }
+        }
+        self.diverted = true;
+    }
+
+    /// Defer best-price rescans after the best level is removed, for feeds
+    /// where the touch flickers out and straight back in. With a non-zero
+    /// `max_deferred`, removing a side's best level leaves its price as the
+    /// cached best, with quantity 0, marked stale (`is_best_stale`), instead
+    /// of scanning for the next level. While stale, `get_best_*`,
+    /// `get_spread`, `bbo_snapshot` and anything else built on the cached
+    /// best report that last known price, which may no longer be populated;
+    /// level and quantity queries are unaffected. The stale best is replaced
+    /// as soon as a level at or through it is set, and otherwise rescanned
+    /// after `max_deferred` further `set`/`remove` calls, or on
+    /// `refresh_best`, which always yields the correct current best.
+    /// 0 (the default) turns it off; changing the setting refreshes first
+    pub fn set_sticky_best(&mut self, max_deferred: u32) {
+        self.refresh_best();
+        self.sticky_limit = max_deferred;
+    }
+
+    pub fn sticky_best(&self) -> u32 {
+        self.sticky_limit
+    }
+
+    /// Whether the cached best on a side may be a stale sticky best
+    pub fn is_best_stale(&self, side: Side) -> bool {
+        match side {
+            Side::Bid => self.stale_bid,
+            Side::Ask => self.stale_ask,
+        }
+    }
+
+    /// Rescan any stale sticky best now, so the cached bests are exact
+    pub fn refresh_best(&mut self) {
+        if self.stale_bid {
+            self.stale_bid = false;
+            self.recompute_best_bid();
+        }
+        if self.stale_ask {
+            self.stale_ask = false;
+            self.recompute_best_ask();
+        }
+        self.stale_updates = 0;
+        self.update_diverted();
+    }
+
+    // Updates arriving during a halt are off the hot path
+    #[cold]
+    fn hold(&mut self, price: Price, quantity: Q, side: Side) {
+        if self.halt_policy == HaltPolicy::Buffer {
+            self.halt_buffer.push((price, quantity, side));
+        }
+    }
+
+    /// Choose what happens to updates that arrive while halted
+    /// Takes effect for updates received after the call
+    pub fn set_halt_policy(&mut self, policy: HaltPolicy) {
+        self.halt_policy = policy;
+    }
+
+    pub fn halt_policy(&self) -> HaltPolicy {
+        self.halt_policy
+    }
+
+    /// Close the book: levels stay readable, but `apply_update` stops
+    /// changing them until `resume`
+    pub fn halt(&mut self) {
+        self.halted = true;
+        self.diverted = true;
+    }
+
+    /// Reopen the book, applying any updates buffered during the halt in
+    /// arrival order, each exactly like a live `set`. Returns how many were
+    /// applied
+    pub fn resume(&mut self) -> usize {
+        self.halted = false;
+        self.update_diverted();
+        let held = std::mem::take(&mut self.halt_buffer);
+        let count = held.len();
+        for (price, quantity, side) in held {
+            self.set(price, quantity, side);
+        }
+        count
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Number of updates buffered since the book was halted
+    pub fn halt_pending(&self) -> usize {
+        self.halt_buffer.len()
+    }
+
+    // `set` regardless of halt state
+    #[inline(always)]
+    fn set_level(&mut self, price: Price, quantity: Q, side: Side) {
+        if quantity.is_zero() {
+            self.set_level_zero(price, side);
+            return;
+        }
+        match side {
+            Side::Bid => {
+                self.upsert_bid(price, quantity);
+            }
+            Side::Ask => {
+                self.upsert_ask(price, quantity);
+            }
+        }
+    }
+
+    // A zero-quantity Set is a removal; rarer than a resize, so kept cold
+    #[cold]
+    fn set_level_zero(&mut self, price: Price, side: Side) {
+        self.remove_level(price, side);
+    }
+
+    // `remove` regardless of halt state
+    #[inline(always)]
+    fn remove_level(&mut self, price: Price, side: Side) {
+        match side {
+            Side::Bid => {
+                self.remove_bid(price);
+            }
+            Side::Ask => {
+                self.remove_ask(price);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_spread(&self) -> Option<Price> {
+        let bid = self.best_bid;
+        let ask = self.best_ask;
+        if bid >= 0 && ask >= 0 {
+            Some(ask - bid)
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_best_bid(&self) -> Option<Price> {
+        let bid = self.best_bid;
+        if bid >= 0 { Some(bid) } else { None }
+    }
+
+    #[inline(always)]
+    pub fn get_best_ask(&self) -> Option<Price> {
+        let ask = self.best_ask;
+        if ask >= 0 { Some(ask) } else { None }
+    }
+
+    #[inline(always)]
+    pub fn get_quantity_at(&self, price: Price, side: Side) -> Option<Q> {
+        if !self.in_range(price) {
+            return None;
+        }
+        match side {
+            Side::Bid => {
+                let qty = self.get_bid(price);
+                if !qty.is_zero() { Some(qty) } else { None }
+            }
+            Side::Ask => {
+                let qty = self.get_ask(price);
+                if !qty.is_zero() { Some(qty) } else { None }
+            }
+        }
+    }
+
+    /// Top `n` populated levels on a side, best first, found by walking the
+    /// bitmask: one quantity read per level returned, however far apart
+    pub fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Q)> {
+        let mut result = Vec::with_capacity(n.min(self.level_count(side)));
+        result.extend(self.levels(side).take(n));
+        result
+    }
+
+    #[inline(always)]
+    pub fn get_total_quantity(&self, side: Side) -> Q {
+        match side {
+            Side::Bid => self.total_bid_quantity,
+            Side::Ask => self.total_ask_quantity,
+        }
+    }
+
+    /// Exclusive upper bound on prices this book can index
+    #[inline(always)]
+    pub fn max_price(&self) -> Price {
+        self.bids.len() as Price
+    }
+
+    /// Whether the arrays are indexed highest price first
+    #[inline(always)]
+    pub fn is_inverted(&self) -> bool {
+        self.slot_flip != 0
+    }
+
+    /// Iterate populated levels on a side, best price first
+    pub fn levels(&self, side: Side) -> Levels<'_, Q> {
+        let best = match side {
+            Side::Bid => self.best_bid,
+            Side::Ask => self.best_ask,
+        };
+
+        let down = self.best_at_top(side);
+        if best < 0 {
+            let block = if down { 0 } else { self.num_blocks() - 1 };
+            return Levels {
+                book: self,
+                side,
+                down,
+                block,
+                mask: 0,
+            };
+        }
+
+        let block = self.slot(best) / BLOCK_SIZE;
+        let mask = unsafe { *self.bitmask(side).get_unchecked(block) };
+        Levels {
+            book: self,
+            side,
+            down,
+            block,
+            mask,
+        }
+    }
+
+    /// Iterate populated levels on a side priced strictly worse than `price`
+    /// (below it for bids, above it for asks), nearest first
+    pub fn levels_after(&self, side: Side, price: Price) -> Levels<'_, Q> {
+        let down = self.best_at_top(side);
+        let max = self.max_price() - 1;
+        let (block, mask) = match side {
+            // Every level is worse than a price past the range's better end
+            Side::Bid if price > max => return self.levels(side),
+            Side::Ask if price < 0 => return self.levels(side),
+            // Nothing is worse than a price at or past the worse end
+            Side::Bid if price <= 0 => (if down { 0 } else { self.num_blocks() - 1 }, 0),
+            Side::Ask if price >= max => (if down { 0 } else { self.num_blocks() - 1 }, 0),
+            _ => {
+                let slot = self.slot(price);
+                let bit = slot % BLOCK_SIZE;
+                let worse = if down {
+                    (1u64 << bit) - 1
+                } else {
+                    (!0u64 << bit) << 1
+                };
+                let block = slot / BLOCK_SIZE;
+                (
+                    block,
+                    unsafe { *self.bitmask(side).get_unchecked(block) } & worse,
+                )
+            }
+        };
+        Levels {
+            book: self,
+            side,
+            down,
+            block,
+            mask,
+        }
+    }
+
+    /// Nearest populated level strictly worse than `price`: the highest bid
+    /// below it or the lowest ask above it. A cursor step for walking the book
+    /// from any price, not just the touch
+    #[inline]
+    pub fn next_worse_level(&self, price: Price, side: Side) -> Option<(Price, Q)> {
+        self.levels_after(side, price).next()
+    }
+
+    /// Remove every level on both sides
+    pub fn clear(&mut self) {
+        for block in 0..self.num_blocks() {
+            let start = block * BLOCK_SIZE;
+            let end = (start + BLOCK_SIZE).min(self.bids.len());
+            if self.bitmask_bid[block] != 0 {
+                self.bids[start..end].fill(Q::ZERO);
+                self.bitmask_bid[block] = 0;
+                self.block_sum_bid[block] = Q::ZERO;
+            }
+            if self.bitmask_ask[block] != 0 {
+                self.asks[start..end].fill(Q::ZERO);
+                self.bitmask_ask[block] = 0;
+                self.block_sum_ask[block] = Q::ZERO;
+            }
+        }
+        self.best_bid = -1;
+        self.best_ask = -1;
+        self.best_bid_qty = Q::ZERO;
+        self.best_ask_qty = Q::ZERO;
+        self.stale_bid = false;
+        self.stale_ask = false;
+        self.total_bid_quantity = Q::ZERO;
+        self.total_ask_quantity = Q::ZERO;
+        self.total_bid_notional = Q::ZERO_NOTIONAL;
+        self.total_ask_notional = Q::ZERO_NOTIONAL;
+        self.bid_level_count = 0;
+        self.ask_level_count = 0;
+    }
+
+    /// Best bid price and its quantity, read from cached fields only
+    #[inline(always)]
+    pub fn get_best_bid_with_qty(&self) -> Option<(Price, Q)> {
+        if self.best_bid >= 0 {
+            Some((self.best_bid, self.best_bid_qty))
+        } else {
+            None
+        }
+    }
+
+    /// Best ask price and its quantity, read from cached fields only
+    #[inline(always)]
+    pub fn get_best_ask_with_qty(&self) -> Option<(Price, Q)> {
+        if self.best_ask >= 0 {
+            Some((self.best_ask, self.best_ask_qty))
+        } else {
+            None
+        }
+    }
+
+    /// Cached best bid as stored: the price, or -1 when the bid side is empty
+    /// For FFI callers that can't take an `Option`; prices are never negative,
+    /// so any negative value means "no bid"
+    #[inline(always)]
+    pub fn raw_best_bid(&self) -> i64 {
+        self.best_bid
+    }
+
+    /// Cached best ask as stored: the price, or -1 when the ask side is empty
+    #[inline(always)]
+    pub fn raw_best_ask(&self) -> i64 {
+        self.best_ask
+    }
+
+    /// Number of populated price levels on a side
+    #[inline(always)]
+    pub fn level_count(&self, side: Side) -> usize {
+        match side {
+            Side::Bid => self.bid_level_count,
+            Side::Ask => self.ask_level_count,
+        }
+    }
+
+    /// Total price * quantity resting on a side
+    #[inline(always)]
+    pub fn total_notional(&self, side: Side) -> Q::Notional {
+        match side {
+            Side::Bid => self.total_bid_notional,
+            Side::Ask => self.total_ask_notional,
+        }
+    }
+
+    /// (block index, populated levels) for every non-empty 64-slot bitmask
+    /// block on a side, in array order. Block indices are positions in the
+    /// arrays, so on an inverted book block 0 holds the highest prices; the
+    /// popcounts sum to `level_count`. A debugging view of how spread out the
+    /// side is, i.e. how many empty blocks a best-price rescan may cross
+    pub fn bitmask_summary(&self, side: Side) -> Vec<(usize, u32)> {
+        self.bitmask(side)
+            .iter()
+            .enumerate()
+            .filter(|&(_, &mask)| mask != 0)
+            .map(|(block, mask)| (block, mask.count_ones()))
+            .collect()
+    }
+
+    /// Whether any level is populated on a side within [low, high] inclusive
+    /// Reads only bitmask words, masking the partial blocks at the edges, and
+    /// stops at the first hit. False for an empty or out-of-range band
+    pub fn has_liquidity_in_range(&self, side: Side, low: Price, high: Price) -> bool {
+        let Some((low, high)) = self.slot_band(low, high) else {
+            return false;
+        };
+        let bitmask = self.bitmask(side);
+        (low / BLOCK_SIZE..=high / BLOCK_SIZE)
+            .any(|block| bitmask[block] & Self::band_mask(block, low, high) != 0)
+    }
+}
+
+impl OrderBookImpl {
+    // Apply an update regardless of halt state
+    #[inline(always)]
+    fn apply(&mut self, update: Update) {
+        let (Update::Set { price, .. } | Update::Remove { price, .. }) = update;
+        if !self.in_range(price) {
+            return;
+        }
+        match update {
+            Update::Set {
+                price,
+                quantity,
+                side,
+            } => self.set_level(price, quantity, side),
+            Update::Remove { price, side } => self.remove_level(price, side),
+        }
+    }
+
+    /// Apply an update whose price was validated at the boundary
+    #[inline(always)]
+    pub fn apply_validated(&mut self, update: ValidatedUpdate) {
+        self.apply_update(update.into());
+    }
+
+    /// Apply every update from an iterator in order, returning how many were
+    /// applied (while halted they are held or dropped like single updates)
+    pub fn apply_all<I: IntoIterator<Item = Update>>(&mut self, updates: I) -> usize {
+        let mut applied = 0;
+        for update in updates {
+            self.apply_update(update);
+            applied += 1;
+        }
+        applied
+    }
+
+    /// `apply_all` over a slice, prefetching the level slot of the update
+    /// `PREFETCH_DISTANCE` ahead of the one being applied. Pays off when
+    /// consecutive prices are scattered over more of the book than fits in
+    /// L2; for updates clustered near the touch a plain loop is as fast
+    pub fn apply_batch(&mut self, updates: &[Update]) {
+        for (i, update) in updates.iter().enumerate() {
+            if let Some(Update::Set { price, side, .. } | Update::Remove { price, side }) =
+                updates.get(i + PREFETCH_DISTANCE)
+            {
+                self.prefetch_level(*price, *side);
+            }
+            self.apply_update(update.clone());
+        }
+    }
+
+    /// `apply_all` over fallible items, e.g. a line parser's output. Stops at
+    /// the first error and returns it; updates before it stay applied
+    pub fn try_apply_all<E, I: IntoIterator<Item = Result<Update, E>>>(
+        &mut self,
+        updates: I,
+    ) -> Result<usize, E> {
+        let mut applied = 0;
+        for update in updates {
+            self.apply_update(update?);
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    /// Apply an update and return the signed change it made to the side totals
+    /// as (bid_total, ask_total). The side not touched always reports 0, as do
+    /// updates held or dropped while halted and out-of-range prices
+    #[inline(always)]
+    pub fn apply_update_delta(&mut self, update: Update) -> (i64, i64) {
+        let (price, quantity, side) = match update {
+            Update::Set {
+                price,
+                quantity,
+                side,
+            } => (price, quantity, side),
+            Update::Remove { price, side } => (price, 0, side),
+        };
+        if !self.in_range(price) {
+            return (0, 0);
+        }
+        if self.diverted && self.divert(price, quantity, side) {
+            return (0, 0);
+        }
+        match update {
+            Update::Set {
+                price,
+                quantity,
+                side,
+            } if quantity != 0 => match side {
+                Side::Bid => (quantity as i64 - self.upsert_bid(price, quantity) as i64, 0),
+                Side::Ask => (0, quantity as i64 - self.upsert_ask(price, quantity) as i64),
+            },
+            Update::Set { price, side, .. } | Update::Remove { price, side } => match side {
+                Side::Bid => (-(self.remove_bid(price) as i64), 0),
+                Side::Ask => (0, -(self.remove_ask(price) as i64)),
+            },
+        }
+    }
+
+    /// Set a level to `new_qty` (0 removes it) and return the signed change in
+    /// its quantity, so external aggregates can follow without a read first
+    /// 0 while halted, like `apply_update_delta`
+    #[inline(always)]
+    pub fn update_level(&mut self, price: Price, new_qty: Quantity, side: Side) -> i64 {
+        let (bid, ask) = self.apply_update_delta(Update::Set {
+            price,
+            quantity: new_qty,
+            side,
+        });
+        bid + ask
+    }
+
+    /// Best bid and quantity after verifying them against the price array
+    /// If the bitmask reports a best whose array slot is empty (or the cached
+    /// quantity has drifted), the book repairs itself and counts an anomaly
+    /// rather than returning an inconsistent BBO
+    pub fn get_best_bid_checked(&mut self) -> Option<(Price, Quantity)> {
+        // A stale sticky best is expected to be empty, not an anomaly
+        self.refresh_best();
+        while self.best_bid >= 0 {
+            let qty = self.get_bid(self.best_bid);
+            if qty == 0 {
+                self.anomalies += 1;
+                self.update_bitmask_bid(self.best_bid, false);
+                self.recompute_best_bid();
+                continue;
+            }
+            if qty != self.best_bid_qty {
+                self.anomalies += 1;
+                self.best_bid_qty = qty;
+            }
+            self.update_bitmask_bid(self.best_bid, true);
+            break;
+        }
+        self.get_best_bid_with_qty()
+    }
+
+    /// Ask-side counterpart of `get_best_bid_checked`
+    pub fn get_best_ask_checked(&mut self) -> Option<(Price, Quantity)> {
+        self.refresh_best();
+        while self.best_ask >= 0 {
+            let qty = self.get_ask(self.best_ask);
+            if qty == 0 {
+                self.anomalies += 1;
+                self.update_bitmask_ask(self.best_ask, false);
+                self.recompute_best_ask();
+                continue;
+            }
+            if qty != self.best_ask_qty {
+                self.anomalies += 1;
+                self.best_ask_qty = qty;
+            }
+            self.update_bitmask_ask(self.best_ask, true);
+            break;
+        }
+        self.get_best_ask_with_qty()
+    }
+
+    /// Enable or disable counting of removals at the touch
+    /// Off by default; enabling resets the count
+    pub fn set_flicker_tracking(&mut self, enabled: bool) {
+        self.track_flicker = enabled;
+        if enabled {
+            self.touch_flicker = 0;
+        }
+    }
+
+    /// Number of times the best level on either side was removed (forcing a
+    /// best-price rescan) since tracking was enabled or last reset
+    /// A high count relative to update volume points at a flickering touch
+    pub fn touch_flicker_count(&self) -> u64 {
+        self.touch_flicker
+    }
+
+    /// Restart the flicker window
+    pub fn reset_flicker_count(&mut self) {
+        self.touch_flicker = 0;
+    }
+
+    /// Enable or disable per-level timestamps recorded by `apply_update_at`
+    /// Off by default since it costs two more price-indexed arrays; enabling
+    /// starts every level at time 0
+    pub fn set_timestamp_tracking(&mut self, enabled: bool) {
+        if enabled {
+            self.level_time_bid = vec![0; self.bids.len()];
+            self.level_time_ask = vec![0; self.asks.len()];
+        } else {
+            self.level_time_bid = Vec::new();
+            self.level_time_ask = Vec::new();
+        }
+    }
+
+    pub fn timestamp_tracking(&self) -> bool {
+        !self.level_time_bid.is_empty()
+    }
+
+    /// Remember the last `capacity` (price, side) levels written through
+    /// `set`/`remove`/`apply_update` for `prefetch_recent`; 0 turns it off
+    /// Off by default since it adds a ring write to every update. Changing
+    /// the capacity forgets the levels recorded so far
+    pub fn set_recent_tracking(&mut self, capacity: usize) {
+        self.recent_touched = Vec::with_capacity(capacity);
+        self.recent_capacity = capacity;
+        self.recent_next = 0;
+        self.update_diverted();
+    }
+
+    /// Recently touched levels, in no particular order
+    pub fn recent_levels(&self) -> &[(Price, Side)] {
+        &self.recent_touched
+    }
+
+    /// Issue cache prefetch hints for the recently touched levels, e.g. after
+    /// an idle gap and before the next burst, which tends to revisit them
+    /// Only a hint: no effect on book state, and a no-op off x86_64
+    pub fn prefetch_recent(&self) {
+        for &(price, side) in &self.recent_touched {
+            self.prefetch_level(price, side);
+        }
+    }
+
+    /// `apply_update` that also stamps the level with `now_ns` when timestamp
+    /// tracking is on, and records spread and midpoint samples when their
+    /// history is on and the value changed. Levels changed through plain `apply_update`, or
+    /// held during a halt, keep their previous stamp
+    #[inline]
+    pub fn apply_update_at(&mut self, update: Update, now_ns: u64) {
+        let stamp = match update {
+            Update::Set {
+                price,
+                quantity,
+                side,
+            } if quantity != 0 && !self.halted => Some((price as usize, side)),
+            _ => None,
+        };
+        self.apply_update(update);
+
+        if let Some((price, side)) = stamp {
+            let times = match side {
+                Side::Bid => &mut self.level_time_bid,
+                Side::Ask => &mut self.level_time_ask,
+            };
+            if let Some(time) = times.get_mut(price) {
+                *time = now_ns;
+            }
+        }
+
+        if self.spread_history_capacity != 0
+            && self.spread_history.back().map(|&(_, spread)| spread) != Some(self.get_spread())
+        {
+            self.record_spread(now_ns);
+        }
+        if self.mid_history_capacity != 0 && self.mid_history.back().copied() != self.get_midpoint()
+        {
+            self.record_midpoint();
+        }
+    }
+
+    /// Keep the last `capacity` (time, spread) samples; 0 turns history off
+    /// Changing the capacity discards existing samples
+    pub fn set_spread_history(&mut self, capacity: usize) {
+        self.spread_history = VecDeque::with_capacity(capacity);
+        self.spread_history_capacity = capacity;
+    }
+
+    /// Record the current spread (None if a side is empty) as of `now_ns`
+    /// No-op while spread history is off
+    pub fn record_spread(&mut self, now_ns: u64) {
+        if self.spread_history_capacity == 0 {
+            return;
+        }
+        if self.spread_history.len() == self.spread_history_capacity {
+            self.spread_history.pop_front();
+        }
+        self.spread_history.push_back((now_ns, self.get_spread()));
+    }
+
+    /// Retained (time ns, spread) samples, oldest first
+    pub fn spread_history(&self) -> &VecDeque<(u64, Option<Price>)> {
+        &self.spread_history
+    }
+
+    /// Keep the last `capacity` midpoint samples; 0 turns history off
+    /// Changing the capacity discards existing samples
+    pub fn set_mid_history(&mut self, capacity: usize) {
+        self.mid_history = VecDeque::with_capacity(capacity);
+        self.mid_history_capacity = capacity;
+    }
+
+    /// Record the current midpoint. No-op while midpoint history is off or
+    /// when there is no positive midpoint (a side empty)
+    pub fn record_midpoint(&mut self) {
+        if self.mid_history_capacity == 0 {
+            return;
+        }
+        let Some(mid) = self.get_midpoint().filter(|&mid| mid > 0.0) else {
+            return;
+        };
+        if self.mid_history.len() == self.mid_history_capacity {
+            self.mid_history.pop_front();
+        }
+        self.mid_history.push_back(mid);
+    }
+
+    /// Retained midpoint samples, oldest first
+    pub fn mid_history(&self) -> &VecDeque<f64> {
+        &self.mid_history
+    }
+
+    /// Standard deviation of the log returns between consecutive midpoint
+    /// samples (per sample, not annualised). None with fewer than two samples
+    pub fn midpoint_realized_vol(&self) -> Option<f64> {
+        if self.mid_history.len() < 2 {
+            return None;
+        }
+        let returns: Vec<f64> = self
+            .mid_history
+            .iter()
+            .zip(self.mid_history.iter().skip(1))
+            .map(|(prev, next)| (next / prev).ln())
+            .collect();
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let variance = returns.iter().map(|r| (r - mean) * (r - mean)).sum::<f64>() / n;
+        Some(variance.sqrt())
+    }
+
+    /// Average spread over the retained history, each sample weighted by how
+    /// long it was in effect (until the next sample). The newest sample has no
+    /// duration yet, and intervals with a side empty are left out
+    /// Returns None with fewer than two samples or no weighted time
+    pub fn time_weighted_avg_spread(&self) -> Option<f64> {
+        let mut weighted = 0.0;
+        let mut elapsed = 0u64;
+        for ((start, spread), (end, _)) in self
+            .spread_history
+            .iter()
+            .zip(self.spread_history.iter().skip(1))
+        {
+            if let Some(spread) = spread {
+                let duration = end.saturating_sub(*start);
+                weighted += *spread as f64 * duration as f64;
+                elapsed += duration;
+            }
+        }
+        if elapsed == 0 {
+            None
+        } else {
+            Some(weighted / elapsed as f64)
+        }
+    }
+
+    /// Percentile rank in [0, 1] of the current spread among the retained
+    /// spread samples: the share of samples narrower than it, counting ties as
+    /// half. Near 1 the spread is wider than almost everything recorded
+    /// Samples taken with a side empty are skipped. Returns None if a side is
+    /// empty now or no usable sample is retained
+    pub fn current_spread_percentile(&self) -> Option<f64> {
+        let current = self.get_spread()?;
+        let mut samples = 0u64;
+        let mut rank = 0u64;
+        for spread in self.spread_history.iter().filter_map(|&(_, spread)| spread) {
+            samples += 1;
+            rank += match spread.cmp(&current) {
+                std::cmp::Ordering::Less => 2,
+                std::cmp::Ordering::Equal => 1,
+                std::cmp::Ordering::Greater => 0,
+            };
+        }
+        if samples == 0 {
+            None
+        } else {
+            Some(rank as f64 / (2 * samples) as f64)
+        }
+    }
+
+    /// Nanoseconds since a populated level was last stamped
+    /// None if the level is empty or timestamp tracking is off
+    pub fn level_age(&self, price: Price, side: Side, now_ns: u64) -> Option<u64> {
+        let times = match side {
+            Side::Bid => &self.level_time_bid,
+            Side::Ask => &self.level_time_ask,
+        };
+        let time = *times.get(usize::try_from(price).ok()?)?;
+        self.get_quantity_at(price, side)?;
+        Some(now_ns.saturating_sub(time))
+    }
+
+    /// Quantity of the top `depth` levels, each discounted by its age as
+    /// qty * 0.5^(age / half_life_ns). A zero half-life keeps only levels
+    /// stamped at `now_ns`. With timestamp tracking off every level counts as
+    /// fresh and this is the plain depth
+    pub fn freshness_weighted_depth(
+        &self,
+        side: Side,
+        depth: usize,
+        now_ns: u64,
+        half_life_ns: u64,
+    ) -> f64 {
+        self.levels(side)
+            .take(depth)
+            .map(|(price, qty)| {
+                let age = self.level_age(price, side, now_ns).unwrap_or(0);
+                let weight = if age == 0 {
+                    1.0
+                } else if half_life_ns == 0 {
+                    0.0
+                } else {
+                    (-(age as f64 / half_life_ns as f64)).exp2()
+                };
+                quantity_to_f64_checked(qty) * weight
+            })
+            .sum()
+    }
+
+    /// Full consistency check of the cached state against the price arrays:
+    /// bitmasks, block sums, level counts, totals, best prices/quantities and
+    /// an uncrossed touch. O(price range), intended for tests and debugging
+    pub fn check_invariants(&self) -> Result<(), String> {
+        for side in [Side::Bid, Side::Ask] {
+            let quantities = self.quantities(side);
+            let bitmask = self.bitmask(side);
+            let block_sums = self.block_sums(side);
+
+            let mut total: Quantity = 0;
+            let mut notional: u128 = 0;
+            let mut count = 0;
+            let mut best: Price = -1;
+            for block in 0..self.num_blocks() {
+                let start = block * BLOCK_SIZE;
+                let end = (start + BLOCK_SIZE).min(quantities.len());
+
+                // Fast path for the common all-empty block
+                const EMPTY: [Quantity; BLOCK_SIZE] = [0; BLOCK_SIZE];
+                if bitmask[block] == 0
+                    && block_sums[block] == 0
+                    && quantities[start..end] == EMPTY[..end - start]
+                {
+                    continue;
+                }
+
+                let mut populated = 0u64;
+                let mut sum: Quantity = 0;
+                for (bit, &qty) in quantities[start..end].iter().enumerate() {
+                    if qty != 0 {
+                        populated |= 1 << bit;
+                        sum = sum.wrapping_add(qty);
+                        notional += self.price_of(start + bit) as u128 * qty as u128;
+                    }
+                }
+
+                if populated != bitmask[block] {
+                    return Err(format!(
+                        "{side:?} block {block}: bitmask {:#018x} but populated {populated:#018x}",
+                        bitmask[block]
+                    ));
+                }
+                if sum != block_sums[block] {
+                    return Err(format!(
+                        "{side:?} block {block}: block sum {} but levels sum to {sum}",
+                        block_sums[block]
+                    ));
+                }
+
+                if populated != 0 {
+                    let low = self.price_of(start + populated.trailing_zeros() as usize);
+                    let high = self.price_of(start + 63 - populated.leading_zeros() as usize);
+                    best = match side {
+                        Side::Bid => best.max(low.max(high)),
+                        Side::Ask if best < 0 => low.min(high),
+                        Side::Ask => best.min(low.min(high)),
+                    };
+                }
+                count += populated.count_ones() as usize;
+                total = total.wrapping_add(sum);
+            }
+
+            let (cached_best, cached_qty) = match side {
+                Side::Bid => (self.best_bid, self.best_bid_qty),
+                Side::Ask => (self.best_ask, self.best_ask_qty),
+            };
+            let best_qty = if best >= 0 {
+                quantities[self.slot(best)]
+            } else {
+                0
+            };
+            // A stale sticky best may also be an emptied price with nothing
+            // beyond it
+            let stale_ok = self.is_best_stale(side)
+                && cached_qty == 0
+                && quantities[self.slot(cached_best)] == 0
+                && match side {
+                    Side::Bid => best < cached_best,
+                    Side::Ask => best < 0 || best > cached_best,
+                };
+            let consistent = stale_ok || (cached_best == best && cached_qty == best_qty);
+            if !consistent {
+                return Err(format!(
+                    "{side:?} best cached as ({cached_best}, {cached_qty}) but book has ({best}, {best_qty})"
+                ));
+            }
+            if self.get_total_quantity(side) != total {
+                return Err(format!(
+                    "{side:?} total cached as {} but levels sum to {total}",
+                    self.get_total_quantity(side)
+                ));
+            }
+            if self.total_notional(side) != notional {
+                return Err(format!(
+                    "{side:?} notional cached as {} but levels sum to {notional}",
+                    self.total_notional(side)
+                ));
+            }
+            if self.level_count(side) != count {
+                return Err(format!(
+                    "{side:?} level count cached as {} but {count} levels are populated",
+                    self.level_count(side)
+                ));
+            }
+        }
+        if let Some(cross) = self.cross_amount() {
+            return Err(format!(
+                "book crossed by {cross}: bid {} over ask {}",
+                self.best_bid, self.best_ask
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number of internal inconsistencies repaired so far
+    pub fn anomaly_count(&self) -> u64 {
+        self.anomalies
+    }
+
+    /// Drop every level beyond the best `max_levels_per_side` on each side
+    /// Only the far end of the book is trimmed, so bests are unaffected
+    /// (unless `max_levels_per_side` is 0, which empties the book)
+    pub fn truncate_depth(&mut self, max_levels_per_side: usize) {
+        let far_bids: Vec<Price> = self
+            .levels(Side::Bid)
+            .skip(max_levels_per_side)
+            .map(|(price, _)| price)
+            .collect();
+        for price in far_bids {
+            self.remove_bid(price);
+        }
+
+        let far_asks: Vec<Price> = self
+            .levels(Side::Ask)
+            .skip(max_levels_per_side)
+            .map(|(price, _)| price)
+            .collect();
+        for price in far_asks {
+            self.remove_ask(price);
+        }
+    }
+
+    /// Last applied sequence number
+    pub fn sequence(&self) -> u64 {
+        self.seq
+    }
+
+    /// Best prices, their quantities and the sequence, from cached fields only
+    #[inline]
+    pub fn bbo_snapshot(&self) -> BboSnapshot {
+        BboSnapshot {
+            best_bid: self.best_bid,
+            bid_qty: if self.best_bid >= 0 {
+                self.best_bid_qty
+            } else {
+                0
+            },
+            best_ask: self.best_ask,
+            ask_qty: if self.best_ask >= 0 {
+                self.best_ask_qty
+            } else {
+                0
+            },
+            seq: self.seq,
+        }
+    }
+
+    /// Replace the whole book with a snapshot taken at `snapshot_seq`
+    /// Buffered updates with a sequence <= `snapshot_seq` are already
+    /// included in the snapshot and will be dropped by `apply_update_seq`;
+    /// updates held during a halt predate it and are discarded
+    pub fn resync(
+        &mut self,
+        bids: &[(Price, Quantity)],
+        asks: &[(Price, Quantity)],
+        snapshot_seq: u64,
+    ) {
+        self.load_levels(bids, asks);
+        self.seq = snapshot_seq;
+
+        // Everything may have changed across a resync
+        self.change_log.clear();
+        self.change_log_floor = snapshot_seq;
+    }
+
+    /// Capture the book as a `BookSnapshot`. A stale sticky best isn't kept:
+    /// the snapshot holds levels only, so it restores as if refreshed
+    pub fn snapshot(&self) -> BookSnapshot {
+        BookSnapshot {
+            max_price: self.max_price(),
+            seq: self.seq,
+            bids: self.all_levels(Side::Bid),
+            asks: self.all_levels(Side::Ask),
+            total_bid_quantity: self.total_bid_quantity,
+            total_ask_quantity: self.total_ask_quantity,
+        }
+    }
+
+    /// Fresh book over `snapshot.max_price` holding its levels, with the
+    /// bitmasks, bests, totals and counts rebuilt from them: every query
+    /// answers as it did on the book snapshotted. Levels go through the
+    /// normal update path, so a damaged snapshot's out-of-range or crossed
+    /// levels are dropped like any such update; the stored totals aren't
+    /// trusted, so compare `snapshot()` of the result to detect one
+    /// Panics if `max_price` is not a valid `with_capacity` range
+    pub fn restore(snapshot: &BookSnapshot) -> OrderBookImpl {
+        let max_price = usize::try_from(snapshot.max_price).unwrap_or(0);
+        let mut book = OrderBookImpl::with_capacity(max_price);
+        book.resync(&snapshot.bids, &snapshot.asks, snapshot.seq);
+        book
+    }
+
+    /// Compact binary image of the populated levels, sequence number and layout
+    /// Layout, all little endian:
+    ///   magic    8 bytes   "OBSNAP02"
+    ///   seq      u64
+    ///   range    u64 `max_price()`, u64 flags (bit 0: `is_inverted()`)
+    ///   counts   u64 bid levels, u64 ask levels
+    ///   levels   (price i64, quantity u64) per level, bids then asks, best first
+    ///   check    u64       `fingerprint()` of the book, verified on load
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        let levels = self.bid_level_count + self.ask_level_count;
+        let mut out = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 48 + levels * 16);
+        let flags = if self.is_inverted() {
+            SNAPSHOT_INVERTED
+        } else {
+            0
+        };
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&self.seq.to_le_bytes());
+        out.extend_from_slice(&(self.max_price() as u64).to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&(self.bid_level_count as u64).to_le_bytes());
+        out.extend_from_slice(&(self.ask_level_count as u64).to_le_bytes());
+        for side in [Side::Bid, Side::Ask] {
+            for (price, qty) in self.levels(side) {
+                out.extend_from_slice(&price.to_le_bytes());
+                out.extend_from_slice(&qty.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&self.fingerprint().to_le_bytes());
+        out
+    }
+
+    /// Rebuild a book from `to_snapshot` output, with the range and layout it
+    /// was written with. Version 1 blobs load as a default-range book.
+    /// Fails with InvalidData on a truncated or corrupted blob, including an
+    /// unusable range, any level outside it or a fingerprint mismatch; the
+    /// blob is fully checked before the book is allocated
+    pub fn from_snapshot(bytes: &[u8]) -> io::Result<OrderBookImpl> {
+        fn invalid(reason: &str) -> io::Error {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad snapshot: {reason}"),
+            )
+        }
+
+        let (body, versioned) = if let Some(body) = bytes.strip_prefix(SNAPSHOT_MAGIC.as_slice()) {
+            (body, true)
+        } else if let Some(body) = bytes.strip_prefix(SNAPSHOT_MAGIC_V1.as_slice()) {
+            (body, false)
+        } else {
+            return Err(invalid("missing magic"));
+        };
+        if body.len() % 8 != 0 {
+            return Err(invalid("truncated"));
+        }
+        let mut words = body
+            .chunks(8)
+            .map(|chunk| <[u8; 8]>::try_from(chunk).map(u64::from_le_bytes));
+        let mut next = || {
+            words
+                .next()
+                .and_then(Result::ok)
+                .ok_or_else(|| invalid("truncated"))
+        };
+
+        let seq = next()?;
+        let (max_price, flags) = if versioned {
+            (next()?, next()?)
+        } else {
+            (DEFAULT_MAX_PRICE as u64, 0)
+        };
+        if max_price == 0 || max_price > SNAPSHOT_MAX_PRICE || flags & !SNAPSHOT_INVERTED != 0 {
+            return Err(invalid("bad range"));
+        }
+        let bid_count = next()?;
+        let ask_count = next()?;
+        if bid_count > max_price || ask_count > max_price {
+            return Err(invalid("level count out of range"));
+        }
+
+        let mut sides = [Vec::new(), Vec::new()];
+        for (levels, count) in sides.iter_mut().zip([bid_count, ask_count]) {
+            for _ in 0..count {
+                let price = next()? as Price;
+                let qty = next()?;
+                if !(0..max_price as Price).contains(&price) || qty == 0 {
+                    return Err(invalid("level out of range"));
+                }
+                levels.push((price, qty));
+            }
+        }
+        let check = next()?;
+        if next().is_ok() {
+            return Err(invalid("trailing data"));
+        }
+        if fingerprint_levels([sides[0].iter().copied(), sides[1].iter().copied()]) != check {
+            return Err(invalid("fingerprint mismatch"));
+        }
+
+        let mut book =
+            OrderBookImpl::with_layout(max_price as usize, flags & SNAPSHOT_INVERTED != 0);
+        book.resync(&sides[0], &sides[1], seq);
+        // Levels resync drops (crossed or duplicated) still count as corruption
+        if book.fingerprint() != check {
+            return Err(invalid("fingerprint mismatch"));
+        }
+        Ok(book)
+    }
+
+    /// Apply an update carrying a feed sequence number
+    /// Returns Ok(false) if the update is stale (seq <= last applied) and was dropped,
+    /// Ok(true) if it was applied, or a SequenceGap error if updates were missed
+    /// (the book is left untouched and needs a resync)
+    pub fn apply_update_seq(&mut self, update: Update, seq: u64) -> Result<bool, OrderBookError> {
+        if seq <= self.seq {
+            return Ok(false);
+        }
+        if seq != self.seq + 1 {
+            return Err(OrderBookError::SequenceGap {
+                expected: self.seq + 1,
+                received: seq,
+            });
+        }
+        let (price, side) = match update {
+            Update::Set { price, side, .. } | Update::Remove { price, side } => (price, side),
+        };
+        let cleared = self.crossed_by(&update);
+        self.apply_update(update);
+        self.seq = seq;
+
+        self.log_change(seq, price, side);
+        for (price, side) in cleared {
+            self.log_change(seq, price, side);
+        }
+        Ok(true)
+    }
+
+    fn log_change(&mut self, seq: u64, price: Price, side: Side) {
+        if self.change_log.len() == CHANGE_LOG_CAPACITY
+            && let Some((evicted, _, _)) = self.change_log.pop_front()
+        {
+            self.change_log_floor = evicted;
+        }
+        self.change_log.push_back((seq, price, side));
+    }
+
+    // Opposite levels `update` will clear by pricing through them (see
+    // `uncross`). Empty, without allocating, unless it crosses the touch
+    fn crossed_by(&self, update: &Update) -> Vec<(Price, Side)> {
+        let Update::Set {
+            price,
+            quantity,
+            side,
+        } = *update
+        else {
+            return Vec::new();
+        };
+        // A stale sticky best lies on the near side of the real one, so
+        // checking against it never misses a cross
+        let crosses = match side {
+            Side::Bid => self.best_ask >= 0 && price > self.best_ask,
+            Side::Ask => price < self.best_bid,
+        };
+        if !crosses || quantity == 0 || self.halted || !self.in_range(price) {
+            return Vec::new();
+        }
+        let opposite = match side {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        };
+        self.levels(opposite)
+            .take_while(|&(level, _)| match side {
+                Side::Bid => level < price,
+                Side::Ask => level > price,
+            })
+            .map(|(level, _)| (level, opposite))
+            .collect()
+    }
+
+    /// Apply a sequenced update that may arrive slightly out of order
+    /// Updates up to REORDER_WINDOW ahead of the next expected sequence are
+    /// buffered and applied as soon as the gap before them fills.
+    /// Returns the sequences discarded as duplicates or already applied
+    /// (including buffered ones overtaken by a resync), or a SequenceGap error
+    /// if `seq` is beyond the window and the book needs a resync
+    pub fn apply_update_ordered(
+        &mut self,
+        update: Update,
+        seq: u64,
+    ) -> Result<Vec<u64>, OrderBookError> {
+        let fresh = self.reorder_buffer.split_off(&(self.seq + 1));
+        let mut dropped: Vec<u64> = self.reorder_buffer.keys().copied().collect();
+        self.reorder_buffer = fresh;
+
+        if seq <= self.seq || self.reorder_buffer.contains_key(&seq) {
+            dropped.push(seq);
+            return Ok(dropped);
+        }
+        if seq > self.seq + REORDER_WINDOW {
+            return Err(OrderBookError::SequenceGap {
+                expected: self.seq + 1,
+                received: seq,
+            });
+        }
+
+        self.reorder_buffer.insert(seq, update);
+        while let Some(next) = self.reorder_buffer.remove(&(self.seq + 1)) {
+            self.apply_update_seq(next, self.seq + 1)?;
+        }
+        Ok(dropped)
+    }
+
+    /// Number of out-of-order updates waiting for a gap to fill
+    pub fn reorder_pending(&self) -> usize {
+        self.reorder_buffer.len()
+    }
+
+    /// Levels touched by sequenced updates after `seq`, each listed once
+    /// in order of first change. Fails if `seq` is older than the retained window
+    pub fn changed_prices_since(&self, seq: u64) -> Result<Vec<(Price, Side)>, OrderBookError> {
+        if seq < self.change_log_floor {
+            return Err(OrderBookError::SequenceOutOfWindow {
+                requested: seq,
+                oldest: self.change_log_floor,
+            });
+        }
+
+        let mut seen = HashSet::new();
+        let mut changed = Vec::new();
+        for &(_, price, side) in self.change_log.iter().filter(|(s, _, _)| *s > seq) {
+            if seen.insert((price, side)) {
+                changed.push((price, side));
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Whether applying `update` would change the best bid/ask price or size
+    /// Consistent with comparing `get_best_*_with_qty` before and after `apply_update`
+    #[inline]
+    pub fn would_change_bbo(&self, update: &Update) -> bool {
+        // Held or dropped while halted
+        if self.halted {
+            return false;
+        }
+        let (price, quantity, side) = match *update {
+            Update::Set {
+                price,
+                quantity,
+                side,
+            } => (price, quantity, side),
+            Update::Remove { price, side } => (price, 0, side),
+        };
+        // Ignored by apply_update
+        if !self.in_range(price) {
+            return false;
+        }
+        // Sticky mode past its deferral limit: any update first rescans the
+        // stale bests, which moves the BBO whatever the update does
+        if self.stale_updates >= self.sticky_limit {
+            let moved = |stale: bool, side: Side, best: Price, best_qty: Quantity| {
+                stale && {
+                    let real = self.scan_best(side, best);
+                    real != best || self.get_quantity_at(real, side).unwrap_or(0) != best_qty
+                }
+            };
+            if moved(self.stale_bid, Side::Bid, self.best_bid, self.best_bid_qty)
+                || moved(self.stale_ask, Side::Ask, self.best_ask, self.best_ask_qty)
+            {
+                return true;
+            }
+        }
+        let (best, best_qty) = match side {
+            Side::Bid => (self.best_bid, self.best_bid_qty),
+            Side::Ask => (self.best_ask, self.best_ask_qty),
+        };
+
+        if best >= 0 && price == best {
+            return quantity != best_qty;
+        }
+        if quantity == 0 {
+            return false;
+        }
+        match side {
+            Side::Bid => price > best,
+            Side::Ask => best < 0 || price < best,
+        }
+    }
+
+    /// Apply an update and return the new top of book if the best bid/ask
+    /// price or size changed, None otherwise (including updates held or
+    /// dropped while halted). Compares cached fields only, so the unchanged
+    /// case costs two snapshot reads on top of `apply_update`
+    #[inline]
+    pub fn apply_and_publish(&mut self, update: Update) -> Option<BboSnapshot> {
+        let before = self.bbo_snapshot();
+        self.apply_update(update);
+        let after = self.bbo_snapshot();
+        (after != before).then_some(after)
+    }
+
+    /// Whether a limit order at `price` on `side` would immediately trade
+    /// against the opposite best (the check a post-only order needs)
+    /// Returns false if the opposite side is empty
+    #[inline(always)]
+    pub fn is_marketable(&self, price: Price, side: Side) -> bool {
+        match side {
+            Side::Bid => self.best_ask >= 0 && price >= self.best_ask,
+            Side::Ask => self.best_bid >= 0 && price <= self.best_bid,
+        }
+    }
+
+    /// The populated side when exactly one side has quotes
+    /// None when both sides are populated or the book is completely empty
+    #[inline(always)]
+    pub fn is_one_sided(&self) -> Option<Side> {
+        match (self.best_bid >= 0, self.best_ask >= 0) {
+            (true, false) => Some(Side::Bid),
+            (false, true) => Some(Side::Ask),
+            _ => None,
+        }
+    }
+
+    /// Set (or clear) the reference price used by reference-relative queries
+    pub fn set_reference_price(&mut self, price: Option<Price>) {
+        self.reference_price = price;
+    }
+
+    pub fn reference_price(&self) -> Option<Price> {
+        self.reference_price
+    }
+
+    /// Distance of each best from the reference price in ticks:
+    /// (reference - best_bid, best_ask - reference). Positive values mean the
+    /// touch sits outside the reference on that side
+    /// Returns None if no reference is set or either side is empty
+    pub fn best_distance_from_reference(&self) -> Option<(Price, Price)> {
+        let reference = self.reference_price?;
+        if self.best_bid < 0 || self.best_ask < 0 {
+            return None;
+        }
+        let tick = self.tick_size();
+        Some((
+            (reference - self.best_bid) / tick,
+            (self.best_ask - reference) / tick,
+        ))
+    }
+
+    /// Midpoint of the best bid and ask, None if either side is empty
+    #[inline(always)]
+    pub fn get_midpoint(&self) -> Option<f64> {
+        if self.best_bid >= 0 && self.best_ask >= 0 {
+            Some((self.best_bid + self.best_ask) as f64 / 2.0)
+        } else {
+            None
+        }
+    }
+
+    /// `OrderBook::mid_price` from the cached bests
+    #[inline(always)]
+    pub fn mid_price(&self) -> Option<Price> {
+        if self.best_bid >= 0 && self.best_ask >= 0 {
+            Some(self.best_bid + (self.best_ask - self.best_bid) / 2)
+        } else {
+            None
+        }
+    }
+
+    /// `OrderBook::microprice` from the cached bests and their quantities,
+    /// without touching the level arrays
+    #[inline]
+    pub fn microprice(&self) -> Option<f64> {
+        if self.best_bid >= 0 && self.best_ask >= 0 {
+            microprice_of(
+                self.best_bid,
+                self.best_bid_qty,
+                self.best_ask,
+                self.best_ask_qty,
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Spread as a fraction of the midpoint in basis points
+    /// None if either side is empty or the midpoint is zero
+    #[inline]
+    pub fn get_spread_bps(&self) -> Option<f64> {
+        self.spread_over_mid().map(|ratio| ratio * 10_000.0)
+    }
+
+    /// Spread as a percentage of the midpoint
+    /// None if either side is empty or the midpoint is zero
+    #[inline]
+    pub fn get_spread_pct(&self) -> Option<f64> {
+        self.spread_over_mid().map(|ratio| ratio * 100.0)
+    }
+
+    #[inline(always)]
+    fn spread_over_mid(&self) -> Option<f64> {
+        let mid = self.get_midpoint()?;
+        if mid == 0.0 {
+            None
+        } else {
+            Some((self.best_ask - self.best_bid) as f64 / mid)
+        }
+    }
+
+    /// How far the book is crossed (best_bid - best_ask) when it is crossed
+    /// Returns None if either side is empty or bid <= ask. A new level priced
+    /// through the opposite touch clears the levels it crosses, so this is
+    /// always None for a consistent book; `check_invariants` relies on it
+    #[inline(always)]
+    pub fn cross_amount(&self) -> Option<Price> {
+        if self.best_bid >= 0 && self.best_ask >= 0 && self.best_bid > self.best_ask {
+            Some(self.best_bid - self.best_ask)
+        } else {
+            None
+        }
+    }
+
+    /// Widest run of consecutive empty prices between the lowest bid and the
+    /// highest ask, as (first, last) empty price inclusive. The spread itself is
+    /// one candidate; holes behind either touch are the others. Ties go to the
+    /// lower price. None if the book is crossed, a side is empty or no price in
+    /// that range is empty
+    pub fn largest_gap(&self) -> Option<(Price, Price)> {
+        if self.best_bid < 0 || self.best_ask < 0 || self.best_bid >= self.best_ask {
+            return None;
+        }
+
+        // Bids all sit below asks, so one pass over the combined bitmask visits
+        // every populated price in order (descending in an inverted book)
+        let mut previous: Option<Price> = None;
+        let mut widest: Option<(Price, Price)> = None;
+        for block in 0..self.num_blocks() {
+            let mut mask = self.bitmask_bid[block] | self.bitmask_ask[block];
+            while mask != 0 {
+                let price = self.price_of(block * BLOCK_SIZE + mask.trailing_zeros() as usize);
+                mask &= mask - 1;
+                if let Some(previous) = previous {
+                    let (low, high) = (previous.min(price) + 1, previous.max(price) - 1);
+                    if low <= high
+                        && widest.is_none_or(|(first, last)| {
+                            high - low > last - first || (high - low == last - first && low < first)
+                        })
+                    {
+                        widest = Some((low, high));
+                    }
+                }
+                previous = Some(price);
+            }
+        }
+        widest
+    }
+
+    /// Spread after a market order takes `quantity` from `side`, measured from
+    /// the swept side's new touch to the untouched opposite best. A partly
+    /// filled level stays the touch; a fully filled one hands it to the next.
+    /// None if the sweep exhausts the side or the opposite side is empty
+    pub fn post_sweep_spread(&self, side: Side, quantity: Quantity) -> Option<Price> {
+        let mut remaining = quantity;
+        let touch = self.levels(side).find_map(|(price, qty)| {
+            if remaining < qty {
+                Some(price)
+            } else {
+                remaining -= qty;
+                None
+            }
+        })?;
+
+        match side {
+            Side::Bid => self.get_best_ask().map(|ask| ask - touch),
+            Side::Ask => self.get_best_bid().map(|bid| touch - bid),
+        }
+    }
+
+    /// Take up to `quantity` from `side`, best level first, and remove what was
+    /// taken from the book. Fully consumed levels are cleared with one best
+    /// recompute; a partly consumed level keeps the remainder. A halted book
+    /// fills nothing
+    pub fn execute_market_order(&mut self, side: Side, quantity: Quantity) -> ExecutionReport {
+        let mut report = ExecutionReport {
+            fills: Vec::new(),
+            filled: 0,
+            requested: quantity,
+            avg_price: 0.0,
+        };
+        if self.halted {
+            return report;
+        }
+
+        let mut notional: u128 = 0;
+        let mut rest = None;
+        for (price, qty) in self.levels(side) {
+            let remaining = quantity - report.filled;
+            if remaining == 0 {
+                break;
+            }
+            let take = qty.min(remaining);
+            if take < qty {
+                rest = Some((price, qty - take));
+            }
+            report.fills.push((price, take));
+            report.filled += take;
+            notional += price as u128 * take as u128;
+        }
+        if report.filled != 0 {
+            report.avg_price = notional as f64 / quantity_to_f64_checked(report.filled);
+        }
+
+        let mut cleared: Vec<(Price, Side)> = report
+            .fills
+            .iter()
+            .map(|&(price, _)| (price, side))
+            .collect();
+        if let Some((price, left)) = rest {
+            cleared.pop();
+            self.set_level(price, left, side);
+        }
+        self.remove_prices(&cleared);
+        report
+    }
+
+    /// Market-impact curve for taking up to `max_quantity` from `side`: one
+    /// (cumulative quantity, VWAP so far) point per level walked from best,
+    /// the last one cut at `max_quantity`. VWAP rises along the ask side and
+    /// falls along the bid side
+    pub fn cost_curve(&self, side: Side, max_quantity: Quantity) -> Vec<(Quantity, f64)> {
+        let mut curve = Vec::new();
+        let mut cumulative: Quantity = 0;
+        let mut notional: u128 = 0;
+        for (price, qty) in self.levels(side) {
+            if cumulative == max_quantity {
+                break;
+            }
+            let take = qty.min(max_quantity - cumulative);
+            cumulative += take;
+            notional += price as u128 * take as u128;
+            curve.push((
+                cumulative,
+                notional as f64 / quantity_to_f64_checked(cumulative),
+            ));
+        }
+        curve
+    }
+
+    /// `OrderBook::fill_cost` in one bitmask walk, reading only the levels the
+    /// fill reaches
+    pub fn fill_cost(&self, side: Side, quantity: Quantity) -> Option<(Quantity, u64)> {
+        fill_from_levels(self.levels(side), quantity)
+    }
+
+    /// Largest quantity that can be taken from `side` with the VWAP staying
+    /// within `max_bps` of the touch price: the sizing inverse of `cost_curve`.
+    /// Walks levels from best in one pass, taking part of the first level that
+    /// would break the budget. Negative budgets count as 0 (the touch level
+    /// only); 0 if the side is empty
+    pub fn max_quantity_within_slippage(&self, side: Side, max_bps: f64) -> Quantity {
+        let mut levels = self.levels(side);
+        let Some((touch, mut cumulative)) = levels.next() else {
+            return 0;
+        };
+        // Budget and running cost both in price units past the touch, so the
+        // VWAP condition is excess <= budget * cumulative
+        let budget = max_bps.max(0.0) / 10_000.0 * touch as f64;
+        let mut excess = 0.0;
+        for (price, qty) in levels {
+            let distance = match side {
+                Side::Bid => touch - price,
+                Side::Ask => price - touch,
+            } as f64;
+            let take = if distance <= budget {
+                qty
+            } else {
+                let room = (budget * cumulative as f64 - excess) / (distance - budget);
+                (room.max(0.0) as Quantity).min(qty)
+            };
+            cumulative = cumulative.saturating_add(take);
+            if take < qty {
+                break;
+            }
+            excess += distance * qty as f64;
+        }
+        cumulative
+    }
+
+    /// `get_top_levels` into a caller-owned buffer, reusing its allocation
+    /// The buffer is cleared first; contents and order match `get_top_levels`
+    pub fn top_levels_into(&self, side: Side, n: usize, out: &mut Vec<(Price, Quantity)>) {
+        out.clear();
+        out.extend(self.levels(side).take(n));
+    }
+
+    /// Depth-of-market ladder: the top `depth` asks from highest to lowest,
+    /// a `Mid` row, then the top `depth` bids from highest to lowest. The mid
+    /// row is left out unless both sides have a level
+    pub fn display_rows(&self, depth: usize) -> Vec<DisplayRow> {
+        let mut rows = Vec::with_capacity(2 * depth + 1);
+        rows.extend(
+            self.levels(Side::Ask)
+                .take(depth)
+                .map(|(price, qty)| DisplayRow::Ask { price, qty }),
+        );
+        rows.reverse();
+        if let (Some(price), Some(spread)) = (self.get_midpoint(), self.get_spread()) {
+            rows.push(DisplayRow::Mid { price, spread });
+        }
+        rows.extend(
+            self.levels(Side::Bid)
+                .take(depth)
+                .map(|(price, qty)| DisplayRow::Bid { price, qty }),
+        );
+        rows
+    }
+
+    /// Every populated level on a side, best first
+    pub fn all_levels(&self, side: Side) -> Vec<(Price, Quantity)> {
+        let mut levels = Vec::with_capacity(self.level_count(side));
+        levels.extend(self.levels(side));
+        levels
+    }
+
+    /// Best two populated levels per side without allocating
+    /// The second level is the next populated price, skipping any gap
+    pub fn get_top_two(&self) -> TopTwo {
+        let mut bids = self.levels(Side::Bid);
+        let mut asks = self.levels(Side::Ask);
+        TopTwo {
+            bid0: bids.next(),
+            bid1: bids.next(),
+            ask0: asks.next(),
+            ask1: asks.next(),
+        }
+    }
+
+    /// VWAP of the whole side in O(1) from the cached totals
+    /// Returns None if the side is empty
+    #[inline]
+    pub fn side_vwap(&self, side: Side) -> Option<f64> {
+        let quantity = self.get_total_quantity(side);
+        if quantity == 0 {
+            None
+        } else {
+            Some(self.total_notional(side) as f64 / quantity_to_f64_checked(quantity))
+        }
+    }
+
+    /// Liquidity centroid of a side: quantity-weighted mean price over every
+    /// populated level. The same quantity as `side_vwap`, so O(1) from the
+    /// cached totals. Returns None if the side is empty
+    #[inline]
+    pub fn center_of_mass(&self, side: Side) -> Option<f64> {
+        self.side_vwap(side)
+    }
+
+    /// Liquidity centroid of both sides together, a slow depth-based anchor
+    /// next to touch-based fair values. Returns None if the book is empty
+    pub fn book_center(&self) -> Option<f64> {
+        let quantity = self.total_bid_quantity as u128 + self.total_ask_quantity as u128;
+        if quantity == 0 {
+            None
+        } else {
+            let notional = self.total_bid_notional + self.total_ask_notional;
+            Some(notional as f64 / quantity as f64)
+        }
+    }
+
+    /// Volume-weighted average price of the top `depth` levels on a side
+    /// Returns None if the side has no liquidity
+    pub fn vwap(&self, side: Side, depth: usize) -> Option<f64> {
+        let mut notional: u128 = 0;
+        let mut quantity: u128 = 0;
+
+        for (price, qty) in self.levels(side).take(depth) {
+            notional += price as u128 * qty as u128;
+            quantity += qty as u128;
+        }
+
+        if quantity == 0 {
+            None
+        } else {
+            Some(notional as f64 / quantity_sum_to_f64_checked(quantity))
+        }
+    }
+
+    /// Quantity-weighted mean distance of a side's liquidity from its best,
+    /// sum(|price - best| * qty) / sum(qty), in price units. Near 0 when size
+    /// clusters at the touch. Returns None if the side is empty
+    pub fn avg_liquidity_distance(&self, side: Side) -> Option<f64> {
+        let best = match side {
+            Side::Bid => self.best_bid,
+            Side::Ask => self.best_ask,
+        };
+        if best < 0 {
+            return None;
+        }
+
+        let weighted: u128 = self
+            .levels(side)
+            .map(|(price, qty)| price.abs_diff(best) as u128 * qty as u128)
+            .sum();
+        Some(weighted as f64 / quantity_to_f64_checked(self.get_total_quantity(side)))
+    }
+
+    /// Asymmetry of where liquidity sits around the mid over the top `depth`
+    /// levels per side: (ask_dist - bid_dist) / (ask_dist + bid_dist), where
+    /// each dist is the quantity-weighted mean distance of that side's levels
+    /// from the midpoint, as `avg_liquidity_distance` measures it from best.
+    /// In [-1, 1]; positive means asks sit farther out (bullish pressure).
+    /// 0.0 if both distances are 0 (a locked touch with depth 1); None if
+    /// either side is empty or `depth` is 0
+    pub fn book_skew(&self, depth: usize) -> Option<f64> {
+        let mid = self.get_midpoint()?;
+        let mean_distance = |side: Side| {
+            let (weighted, total) = self.levels(side).take(depth).fold(
+                (0.0, 0.0),
+                |(weighted, total), (price, qty)| {
+                    let qty = quantity_to_f64_checked(qty);
+                    (weighted + (price as f64 - mid).abs() * qty, total + qty)
+                },
+            );
+            (total > 0.0).then(|| weighted / total)
+        };
+        let bid = mean_distance(Side::Bid)?;
+        let ask = mean_distance(Side::Ask)?;
+        if bid + ask == 0.0 {
+            return Some(0.0);
+        }
+        Some((ask - bid) / (ask + bid))
+    }
+
+    /// Quantity resting at prices strictly better than `price` on a side,
+    /// from the block sums rather than a per-price walk
+    pub fn liquidity_ahead(&self, price: Price, side: Side) -> Quantity {
+        match side {
+            Side::Bid if self.best_bid > price => {
+                self.quantity_in_band(side, price + 1, self.best_bid)
+            }
+            Side::Ask if self.best_ask >= 0 && self.best_ask < price => {
+                self.quantity_in_band(side, self.best_ask, price - 1)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Queue in front of a resting order at `price`: everything at better
+    /// prices (`liquidity_ahead`) plus `my_qty_ahead`, the part of its own
+    /// level the caller knows is ahead of it (an L2 book can't tell). That part
+    /// is capped at the level's current quantity, since the level shrinking
+    /// below it means orders ahead have filled or cancelled
+    pub fn estimated_queue_position(
+        &self,
+        price: Price,
+        side: Side,
+        my_qty_ahead: Quantity,
+    ) -> Quantity {
+        let at_level = self.get_quantity_at(price, side).unwrap_or(0);
+        self.liquidity_ahead(price, side)
+            .saturating_add(my_qty_ahead.min(at_level))
+    }
+
+    /// Total quantity resting at populated prices within [low, high] inclusive,
+    /// saturating at `Quantity::MAX`. A whole 64-price block holding more than
+    /// that reads modulo, like its block sum. Returns 0 for an empty or
+    /// out-of-range band
+    pub fn quantity_in_band(&self, side: Side, low: Price, high: Price) -> Quantity {
+        let Some((low, high)) = self.slot_band(low, high) else {
+            return 0;
+        };
+        let bitmask = self.bitmask(side);
+        let block_sums = self.block_sums(side);
+        let quantities = self.quantities(side);
+
+        // Whole blocks come from the block sums; only the edges are scanned
+        let mut total: Quantity = 0;
+        for block in low / BLOCK_SIZE..=high / BLOCK_SIZE {
+            let band = Self::band_mask(block, low, high);
+            if band == !0 {
+                total = total.saturating_add(block_sums[block]);
+                continue;
+            }
+            let mut mask = bitmask[block] & band;
+            while mask != 0 {
+                let bit = mask.trailing_zeros() as usize;
+                total = total.saturating_add(quantities[block * BLOCK_SIZE + bit]);
+                mask &= mask - 1;
+            }
+        }
+        total
+    }
+
+    /// Populated levels within `radius_ticks` of each best, best first:
+    /// (bids priced >= best_bid - radius, asks priced <= best_ask + radius)
+    /// Only the bitmask blocks covering the window are read
+    pub fn near_touch(&self, radius_ticks: u64) -> (LevelVec, LevelVec) {
+        let radius =
+            (radius_ticks.min(Price::MAX as u64) as Price).saturating_mul(self.tick_size());
+        let bids = if self.best_bid >= 0 {
+            self.levels_in_band(
+                Side::Bid,
+                self.best_bid.saturating_sub(radius),
+                self.best_bid,
+            )
+        } else {
+            Vec::new()
+        };
+        let asks = if self.best_ask >= 0 {
+            self.levels_in_band(
+                Side::Ask,
+                self.best_ask,
+                self.best_ask.saturating_add(radius),
+            )
+        } else {
+            Vec::new()
+        };
+        (bids, asks)
+    }
+
+    // Populated levels within [low, high] inclusive, best first
+    fn levels_in_band(&self, side: Side, low: Price, high: Price) -> Vec<(Price, Quantity)> {
+        let mut levels = Vec::new();
+        let Some((low, high)) = self.slot_band(low, high) else {
+            return levels;
+        };
+        let bitmask = self.bitmask(side);
+        let quantities = self.quantities(side);
+
+        let blocks = low / BLOCK_SIZE..=high / BLOCK_SIZE;
+        if self.best_at_top(side) {
+            for block in blocks.rev() {
+                let mut mask = bitmask[block] & Self::band_mask(block, low, high);
+                while mask != 0 {
+                    let bit = 63 - mask.leading_zeros() as usize;
+                    mask &= !(1u64 << bit);
+                    let slot = block * BLOCK_SIZE + bit;
+                    levels.push((self.price_of(slot), quantities[slot]));
+                }
+            }
+        } else {
+            for block in blocks {
+                let mut mask = bitmask[block] & Self::band_mask(block, low, high);
+                while mask != 0 {
+                    let bit = mask.trailing_zeros() as usize;
+                    mask &= mask - 1;
+                    let slot = block * BLOCK_SIZE + bit;
+                    levels.push((self.price_of(slot), quantities[slot]));
+                }
+            }
+        }
+        levels
+    }
+
+    /// Sum of price * quantity over levels within `max_distance_ticks` ticks
+    /// of the side's best, inclusive: the notional a taker could plausibly reach,
+    /// leaving out far levels that `total_notional` counts. 0 if the side is empty
+    pub fn usable_notional(&self, side: Side, max_distance_ticks: u64) -> u128 {
+        let best = match side {
+            Side::Bid => self.best_bid,
+            Side::Ask => self.best_ask,
+        };
+        let max_distance = max_distance_ticks.saturating_mul(self.tick_size() as u64);
+        self.levels(side)
+            .take_while(|&(price, _)| price.abs_diff(best) <= max_distance)
+            .map(|(price, qty)| price as u128 * qty as u128)
+            .sum()
+    }
+
+    /// Quantity from the best price out to `limit` inclusive
+    /// (bids priced >= limit, asks priced <= limit). 0 if the side is empty
+    pub fn get_depth_until(&self, side: Side, limit: Price) -> Quantity {
+        match side {
+            Side::Bid if self.best_bid >= 0 => self.quantity_in_band(side, limit, self.best_bid),
+            Side::Ask if self.best_ask >= 0 => self.quantity_in_band(side, self.best_ask, limit),
+            _ => 0,
+        }
+    }
+
+    /// First price (walking from best) at which the running quantity reaches
+    /// `pct` of the side's total. `pct` is clamped to [0, 1]; None if the side is empty
+    pub fn price_at_depth_percentile(&self, side: Side, pct: f64) -> Option<Price> {
+        let target = pct.clamp(0.0, 1.0) * self.get_total_quantity(side) as f64;
+
+        let mut running: Quantity = 0;
+        let mut last = None;
+        for (price, qty) in self.levels(side) {
+            running = running.saturating_add(qty);
+            last = Some(price);
+            if running as f64 >= target {
+                break;
+            }
+        }
+        last
+    }
+
+    /// Cumulative quantity sampled at exponentially spaced distances from best
+    /// (1, 2, 4, 8, ... ticks). Each entry is (sample price, quantity resting
+    /// between best and that price inclusive). Empty if the side is empty
+    pub fn log_depth_samples(&self, side: Side, num_samples: usize) -> Vec<(Price, Quantity)> {
+        let mut samples = Vec::with_capacity(num_samples);
+        let best = match side {
+            Side::Bid => self.best_bid,
+            Side::Ask => self.best_ask,
+        };
+        if best < 0 {
+            return samples;
+        }
+
+        // Each sample only adds the band between it and the previous one
+        let mut cumulative: Quantity = 0;
+        let mut covered = best;
+        for i in 0..num_samples.min(63) {
+            let distance = 1i64 << i;
+            let (limit, low, high, next) = match side {
+                Side::Bid => (
+                    best - distance,
+                    best - distance,
+                    covered,
+                    best - distance - 1,
+                ),
+                Side::Ask => (
+                    best + distance,
+                    covered,
+                    best + distance,
+                    best + distance + 1,
+                ),
+            };
+            cumulative = cumulative.saturating_add(self.quantity_in_band(side, low, high));
+            covered = next;
+            samples.push((limit, cumulative));
+        }
+
+        samples
+    }
+
+    /// `OrderBook::imbalance` summed straight off the bitmask walk, without
+    /// collecting the levels; `depth` 1 compares the best quantities alone
+    pub fn imbalance(&self, depth: usize) -> Option<f64> {
+        let sum = |side| {
+            self.levels(side)
+                .take(depth)
+                .map(|(_, qty)| qty as u128)
+                .sum::<u128>()
+        };
+        imbalance_of(sum(Side::Bid), sum(Side::Ask))
+    }
+
+    /// Imbalance (bid - ask) / (bid + ask) for each rank up to `depth`, pairing
+    /// the i-th populated bid with the i-th populated ask. A side that runs out
+    /// contributes zero quantity; a rank empty on both sides reports 0.0
+    pub fn per_level_imbalance(&self, depth: usize) -> Vec<f64> {
+        let mut bids = self.levels(Side::Bid);
+        let mut asks = self.levels(Side::Ask);
+
+        (0..depth)
+            .map(|_| {
+                let bid = bids.next().map_or(0, |(_, qty)| qty) as f64;
+                let ask = asks.next().map_or(0, |(_, qty)| qty) as f64;
+                if bid + ask == 0.0 {
+                    0.0
+                } else {
+                    (bid - ask) / (bid + ask)
+                }
+            })
+            .collect()
+    }
+
+    /// Least-squares slope of `per_level_imbalance(depth)` against rank (0 at
+    /// the touch): imbalance change per level deeper. Positive means buy-side
+    /// pressure grows deeper in the book. Returns None if depth < 2 or either
+    /// side has fewer than `depth` levels
+    pub fn imbalance_gradient(&self, depth: usize) -> Option<f64> {
+        if depth < 2 || self.bid_level_count < depth || self.ask_level_count < depth {
+            return None;
+        }
+        let series = self.per_level_imbalance(depth);
+        let n = depth as f64;
+        let mean_x = (n - 1.0) / 2.0;
+        let mean_y = series.iter().sum::<f64>() / n;
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (rank, imbalance) in series.iter().enumerate() {
+            let dx = rank as f64 - mean_x;
+            covariance += dx * (imbalance - mean_y);
+            variance += dx * dx;
+        }
+        Some(covariance / variance)
+    }
+
+    /// Choose the venue convention `checksum` follows
+    pub fn set_checksum_format(&mut self, format: ChecksumFormat) {
+        self.checksum_format = format;
+    }
+
+    pub fn checksum_format(&self) -> ChecksumFormat {
+        self.checksum_format
+    }
+
+    /// CRC-32 of the top `depth` levels per side, laid out per the configured
+    /// `ChecksumFormat`, for comparing against a venue-published checksum
+    pub fn checksum(&self, depth: usize) -> u32 {
+        use std::fmt::Write;
+
+        let mut text = String::new();
+        match self.checksum_format {
+            ChecksumFormat::Interleaved => {
+                let mut bids = self.levels(Side::Bid).take(depth);
+                let mut asks = self.levels(Side::Ask).take(depth);
+                loop {
+                    let (bid, ask) = (bids.next(), asks.next());
+                    if bid.is_none() && ask.is_none() {
+                        break;
+                    }
+                    for (price, qty) in bid.into_iter().chain(ask) {
+                        if !text.is_empty() {
+                            text.push(':');
+                        }
+                        let _ = write!(text, "{price}:{qty}");
+                    }
+                }
+            }
+            ChecksumFormat::Concatenated => {
+                for side in [Side::Ask, Side::Bid] {
+                    for (price, qty) in self.levels(side).take(depth) {
+                        let _ = write!(text, "{price}{qty}");
+                    }
+                }
+            }
+        }
+        crc32(text.as_bytes())
+    }
+
+    /// Replace the book with a venue snapshot and check it against the
+    /// checksum the venue sent with it. On a mismatch the previous levels are
+    /// restored and `ChecksumMismatch` returned. The sequence number is left
+    /// alone; on success the change history restarts from it and updates held
+    /// during a halt are discarded, like `resync`
+    pub fn load_verified_snapshot(
+        &mut self,
+        bids: &[(Price, Quantity)],
+        asks: &[(Price, Quantity)],
+        expected_crc: u32,
+        depth: usize,
+    ) -> Result<(), OrderBookError> {
+        let previous = (self.all_levels(Side::Bid), self.all_levels(Side::Ask));
+        let held = self.halt_buffer.clone();
+        self.load_levels(bids, asks);
+
+        let actual = self.checksum(depth);
+        if actual != expected_crc {
+            self.load_levels(&previous.0, &previous.1);
+            self.halt_buffer = held;
+            return Err(OrderBookError::ChecksumMismatch {
+                expected: expected_crc,
+                actual,
+            });
+        }
+
+        self.change_log.clear();
+        self.change_log_floor = self.seq;
+        Ok(())
+    }
+
+    // Clear the book and set the given levels, bypassing halt and sequencing
+    // Held updates are dropped: they predate the levels being loaded
+    fn load_levels(&mut self, bids: &[(Price, Quantity)], asks: &[(Price, Quantity)]) {
+        self.clear();
+        self.halt_buffer.clear();
+        for &(price, quantity) in bids {
+            self.apply(Update::Set {
+                price,
+                quantity,
+                side: Side::Bid,
+            });
+        }
+        for &(price, quantity) in asks {
+            self.apply(Update::Set {
+                price,
+                quantity,
+                side: Side::Ask,
+            });
+        }
+    }
+
+    /// Updates that turn this book into `other`: a Set for every level that
+    /// is new or resized, a Remove for every level `other` lacks. Bids first,
+    /// each side in ascending price order. Both books must share an index
+    /// layout and price range (see `with_inverted_index`, `with_capacity`)
+    pub fn diff(&self, other: &OrderBookImpl) -> Vec<Update> {
+        let mut updates = Vec::new();
+        self.for_each_difference(other, |price, side, quantity| {
+            updates.push(match quantity {
+                Some(quantity) => Update::Set {
+                    price,
+                    quantity,
+                    side,
+                },
+                None => Update::Remove { price, side },
+            });
+        });
+        updates.sort_by_key(|update| match *update {
+            Update::Set { price, side, .. } | Update::Remove { price, side } => {
+                (side == Side::Ask, price)
+            }
+        });
+        updates
+    }
+
+    /// `diff(other).len()` without building the updates: levels populated on
+    /// only one side of the comparison come straight from the bitmask XOR;
+    /// only prices populated in both are compared by quantity
+    pub fn diff_size(&self, other: &OrderBookImpl) -> usize {
+        let mut count = 0;
+        self.for_each_difference(other, |_, _, _| count += 1);
+        count
+    }
+
+    // Calls `f(price, side, Some(target quantity))` for each level to set and
+    // `f(price, side, None)` for each level to remove, in slot order
+    fn for_each_difference(
+        &self,
+        other: &OrderBookImpl,
+        mut f: impl FnMut(Price, Side, Option<Quantity>),
+    ) {
+        assert_eq!(
+            (self.is_inverted(), self.max_price()),
+            (other.is_inverted(), other.max_price()),
+            "diff needs both books in the same index layout"
+        );
+        for side in [Side::Bid, Side::Ask] {
+            let (ours, theirs) = (self.bitmask(side), other.bitmask(side));
+            let (our_qty, their_qty) = (self.quantities(side), other.quantities(side));
+            for block in 0..self.num_blocks() {
+                let (a, b) = (ours[block], theirs[block]);
+                let mut changed = a ^ b;
+                let mut shared = a & b;
+                while shared != 0 {
+                    let bit = shared.trailing_zeros() as usize;
+                    shared &= shared - 1;
+                    let slot = block * BLOCK_SIZE + bit;
+                    if our_qty[slot] != their_qty[slot] {
+                        changed |= 1 << bit;
+                    }
+                }
+                while changed != 0 {
+                    let bit = changed.trailing_zeros() as usize;
+                    changed &= changed - 1;
+                    let slot = block * BLOCK_SIZE + bit;
+                    let target = their_qty[slot];
+                    f(self.price_of(slot), side, (target != 0).then_some(target));
+                }
+            }
+        }
+    }
+
+    /// 64-bit fingerprint of every populated level, for replica consistency checks
+    /// Equal books always agree; any differing level changes it with high probability
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint_levels([self.levels(Side::Bid), self.levels(Side::Ask)])
+    }
+
+    /// Microprice generalised to the top `depth` levels of each side:
+    ///   (Q_ask * VWAP_bid + Q_bid * VWAP_ask) / (Q_bid + Q_ask)
+    /// where Q_side is the quantity and VWAP_side the volume-weighted price of
+    /// that side's top `depth` levels. Like the touch microprice it leans
+    /// toward the side with less size, but a thin touch backed by deep size is
+    /// no longer read as thin. Returns None if either side is empty
+    pub fn deep_microprice(&self, depth: usize) -> Option<f64> {
+        let side_stats = |side| {
+            let mut notional = 0u128;
+            let mut quantity = 0u128;
+            for (price, qty) in self.levels(side).take(depth) {
+                notional += price as u128 * qty as u128;
+                quantity += qty as u128;
+            }
+            if quantity == 0 {
+                None
+            } else {
+                let quantity = quantity_sum_to_f64_checked(quantity);
+                Some((notional as f64 / quantity, quantity))
+            }
+        };
+
+        let (bid_vwap, bid_qty) = side_stats(Side::Bid)?;
+        let (ask_vwap, ask_qty) = side_stats(Side::Ask)?;
+        Some((ask_qty * bid_vwap + bid_qty * ask_vwap) / (bid_qty + ask_qty))
+    }
+
+    /// Fair value as the average price of every level on both sides, each
+    /// weighted by qty * exp(-((price - mid) / sigma)^2 / 2), with sigma =
+    /// `sigma_ticks` ticks. Small sigma stays near the touch, large sigma
+    /// approaches the whole-book VWAP. Returns None if either side is empty,
+    /// sigma is not positive, or every weight underflows to zero
+    pub fn kernel_weighted_mid(&self, sigma_ticks: f64) -> Option<f64> {
+        let mid = self.get_midpoint()?;
+        let sigma = sigma_ticks * self.tick_size() as f64;
+        if sigma.is_nan() || sigma <= 0.0 {
+            return None;
+        }
+
+        let mut weighted = 0.0;
+        let mut weights = 0.0;
+        for (price, qty) in self.levels(Side::Bid).chain(self.levels(Side::Ask)) {
+            let z = (price as f64 - mid) / sigma;
+            let weight = quantity_to_f64_checked(qty) * (-0.5 * z * z).exp();
+            weighted += weight * price as f64;
+            weights += weight;
+        }
+        if weights > 0.0 {
+            Some(weighted / weights)
+        } else {
+            None
+        }
+    }
+
+    /// Fair value as the average of the bid and ask VWAPs over `depth` levels
+    /// Returns None if either side lacks liquidity
+    pub fn depth_weighted_mid(&self, depth: usize) -> Option<f64> {
+        let bid = self.vwap(Side::Bid, depth)?;
+        let ask = self.vwap(Side::Ask, depth)?;
+        Some((bid + ask) / 2.0)
+    }
+}
+
+/// Iterator over populated levels of one side, walking the bitmask
+/// from the best price outward one populated level at a time
+pub struct Levels<'a, Q: QuantityLike = Quantity> {
+    book: &'a OrderBookImpl<Q>,
+    side: Side,
+    // Walking toward lower slots (see `best_at_top`)
+    down: bool,
+    block: usize,
+    mask: u64,
+}
+
+impl<Q: QuantityLike> Iterator for Levels<'_, Q> {
+    type Item = (Price, Q);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.mask != 0 {
+                let bit = if self.down {
+                    63 - self.mask.leading_zeros() as usize
+                } else {
+                    self.mask.trailing_zeros() as usize
+                };
+                self.mask &= !(1u64 << bit);
+                let slot = self.block * BLOCK_SIZE + bit;
+                #[cfg(test)]
+                tests::LEVEL_READS.with(|reads| reads.set(reads.get() + 1));
+                let qty = unsafe { *self.book.quantities(self.side).get_unchecked(slot) };
+                return Some((self.book.price_of(slot), qty));
+            }
+            if self.down {
+                if self.block == 0 {
+                    return None;
+                }
+                self.block -= 1;
+            } else {
+                if self.block >= self.book.num_blocks() - 1 {
+                    return None;
+                }
+                self.block += 1;
+            }
+            self.mask = unsafe { *self.book.bitmask(self.side).get_unchecked(self.block) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        // Quantity-array reads made by `Levels`
+        pub(super) static LEVEL_READS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test_block_count_covers_odd_ranges() {
+        for max_price in [1, 63, 64, 65, 127, 129, 4_097, 200_001, 1_000_003] {
+            let blocks = blocks_for(max_price);
+            assert_eq!(blocks, max_price.div_ceil(BLOCK_SIZE));
+            assert!((max_price - 1) / BLOCK_SIZE < blocks);
+            // No slack block past the one holding the highest price
+            assert_eq!((max_price - 1) / BLOCK_SIZE, blocks - 1);
+        }
+        assert_eq!(
+            blocks_for(usize::MAX - BLOCK_SIZE + 1),
+            usize::MAX / BLOCK_SIZE
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn test_block_count_overflow_panics() {
+        blocks_for(usize::MAX);
+    }
+
+    #[test]
+    fn test_same_quantity_set_leaves_state_identical() {
+        // Every array word and cached scalar, flattened in field order
+        fn state(ob: &OrderBookImpl) -> Vec<u128> {
+            let arrays = [
+                &ob.bids,
+                &ob.asks,
+                &ob.bitmask_bid,
+                &ob.bitmask_ask,
+                &ob.block_sum_bid,
+                &ob.block_sum_ask,
+            ];
+            let mut words: Vec<u128> = arrays
+                .iter()
+                .flat_map(|a| a.iter().map(|&w| w as u128))
+                .collect();
+            words.extend([
+                ob.best_bid as u128,
+                ob.best_ask as u128,
+                ob.best_bid_qty as u128,
+                ob.best_ask_qty as u128,
+                ob.total_bid_quantity as u128,
+                ob.total_ask_quantity as u128,
+                ob.total_bid_notional,
+                ob.total_ask_notional,
+                ob.bid_level_count as u128,
+                ob.ask_level_count as u128,
+            ]);
+            words
+        }
+
+        let mut ob = OrderBookImpl::new();
+        for (price, quantity, side) in [
+            (100, 10, Side::Bid),
+            (98, 7, Side::Bid),
+            (105, 4, Side::Ask),
+            (130, 9, Side::Ask),
+        ] {
+            ob.apply_update(Update::Set {
+                price,
+                quantity,
+                side,
+            });
+        }
+
+        let before = state(&ob);
+        for (price, quantity, side) in [
+            (100, 10, Side::Bid),
+            (98, 7, Side::Bid),
+            (105, 4, Side::Ask),
+            (130, 9, Side::Ask),
+        ] {
+            let delta = ob.apply_update_delta(Update::Set {
+                price,
+                quantity,
+                side,
+            });
+            assert_eq!(delta, (0, 0));
+        }
+        assert!(state(&ob) == before);
+    }
+
+    #[test]
+    fn test_recompute_best_bid_crosses_block_boundary() {
+        let mut ob = OrderBookImpl::new();
+        // 127 is the highest bit of block 1; 63 the highest of block 0
+        for price in [127, 64, 63, 0] {
+            ob.apply_update(Update::Set {
+                price,
+                quantity: price as Quantity + 1,
+                side: Side::Bid,
+            });
+        }
+
+        for (removed, next) in [(127, Some(64)), (64, Some(63)), (63, Some(0)), (0, None)] {
+            ob.apply_update(Update::Remove {
+                price: removed,
+                side: Side::Bid,
+            });
+            assert_eq!(ob.get_best_bid(), next);
+            assert_eq!(
+                ob.get_best_bid_with_qty(),
+                next.map(|p| (p, p as Quantity + 1))
+            );
+            assert!(ob.check_invariants().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_recompute_best_ask_crosses_block_boundary() {
+        let mut ob = OrderBookImpl::new();
+        let top = DEFAULT_MAX_PRICE as Price - 1;
+        // 128 is the lowest bit of block 2; 192 the lowest of block 3
+        for price in [128, 191, 192, top] {
+            ob.apply_update(Update::Set {
+                price,
+                quantity: 7,
+                side: Side::Ask,
+            });
+        }
+
+        for (removed, next) in [
+            (128, Some(191)),
+            (191, Some(192)),
+            (192, Some(top)),
+            (top, None),
+        ] {
+            ob.apply_update(Update::Remove {
+                price: removed,
+                side: Side::Ask,
+            });
+            assert_eq!(ob.get_best_ask(), next);
+            assert!(ob.check_invariants().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_highest_price_uses_last_block() {
+        let mut ob = OrderBookImpl::new();
+        let top = DEFAULT_MAX_PRICE as Price - 1;
+        for side in [Side::Bid, Side::Ask] {
+            ob.apply_update(Update::Set {
+                price: top,
+                quantity: 3,
+                side,
+            });
+        }
+        assert_eq!(
+            ob.bitmask_bid[ob.num_blocks() - 1],
+            1u64 << (top as usize % BLOCK_SIZE)
+        );
+        assert_eq!(ob.block_sum_ask[ob.num_blocks() - 1], 3);
+        assert!(ob.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_checked_best_repairs_phantom_levels() {
+        let mut ob = OrderBookImpl::new();
+        for (price, side) in [
+            (100, Side::Bid),
+            (90, Side::Bid),
+            (200, Side::Ask),
+            (300, Side::Ask),
+        ] {
+            ob.apply_update(Update::Set {
+                price,
+                quantity: 10,
+                side,
+            });
+        }
+
+        // Leave set bits behind with no quantity at the best prices
+        ob.set_bid(100, 0);
+        ob.set_ask(200, 0);
+        ob.set_ask(300, 0);
+
+        assert_eq!(ob.get_best_bid_checked(), Some((90, 10)));
+        assert_eq!(ob.get_best_ask_checked(), None);
+        assert_eq!(ob.anomaly_count(), 3);
+        assert_eq!(
+            ob.get_quantity_at(ob.get_best_bid().unwrap(), Side::Bid),
+            Some(10)
+        );
+
+        // Drifted cached quantity is refreshed from the array
+        ob.set_bid(90, 25);
+        assert_eq!(ob.get_best_bid_checked(), Some((90, 25)));
+        assert_eq!(ob.anomaly_count(), 4);
+
+        // A consistent book is left alone
+        assert_eq!(ob.get_best_bid_checked(), Some((90, 25)));
+        assert_eq!(ob.anomaly_count(), 4);
+    }
+
+    #[test]
+    fn test_inverted_index_matches_default_layout() {
+        use crate::workload::XorShift64;
+
+        let mut plain = OrderBookImpl::new();
+        let mut inverted = OrderBookImpl::with_inverted_index(true);
+        assert!(inverted.is_inverted() && !plain.is_inverted());
+
+        // The top price lives at index 0 and in block 0
+        let top = DEFAULT_MAX_PRICE as Price - 1;
+        inverted.apply_update(Update::Set {
+            price: top,
+            quantity: 4,
+            side: Side::Bid,
+        });
+        assert_eq!((inverted.bids[0], inverted.bitmask_bid[0]), (4, 1));
+        inverted.clear();
+
+        // Prices cluster on both sides of a block boundary and near both ends
+        let mut rng = XorShift64::new(7);
+        for step in 0..20_000 {
+            let base = [0, 1_000, 100_000, top - 200][(rng.next_u64() % 4) as usize];
+            let price = base + (rng.next_u64() % 200) as Price;
+            let side = if rng.next_u64().is_multiple_of(2) {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let update = if rng.next_u64().is_multiple_of(3) {
+                Update::Remove { price, side }
+            } else {
+                Update::Set {
+                    price,
+                    quantity: rng.next_u64() % 50,
+                    side,
+                }
+            };
+            plain.apply_update(update.clone());
+            inverted.apply_update(update);
+
+            if step % 500 == 0 {
+                assert!(inverted.check_invariants().is_ok());
+                assert_eq!(plain.get_best_bid(), inverted.get_best_bid());
+                assert_eq!(plain.get_best_ask(), inverted.get_best_ask());
+                assert_eq!(plain.largest_gap(), inverted.largest_gap());
+                assert_eq!(plain.near_touch(300), inverted.near_touch(300));
+                for side in [Side::Bid, Side::Ask] {
+                    assert_eq!(plain.all_levels(side), inverted.all_levels(side));
+                    assert_eq!(
+                        plain.get_top_levels(side, 10),
+                        inverted.get_top_levels(side, 10)
+                    );
+                    assert_eq!(
+                        plain.quantity_in_band(side, 950, 100_150),
+                        inverted.quantity_in_band(side, 950, 100_150)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_invariants_catches_block_sum_drift() {
+        let mut ob = OrderBookImpl::new();
+        for (price, quantity) in [(130, 5), (150, 7), (150, 2), (191, 4)] {
+            ob.apply_update(Update::Set {
+                price,
+                quantity,
+                side: Side::Ask,
+            });
+        }
+        // In-place resizes keep the block sum exact
+        assert_eq!(ob.block_sum_ask[2], 11);
+        assert!(ob.check_invariants().is_ok());
+
+        // A drifted sum is reported even though every level is intact
+        ob.block_sum_ask[2] += 1;
+        let err = ob.check_invariants().unwrap_err();
+        assert!(err.contains("block 2: block sum 12"), "{err}");
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_top_levels_probes_independent_of_gaps() {
+        for gap in [1, 70, 5_000] {
+            for inverted in [false, true] {
+                let mut book: OrderBookImpl = OrderBookImpl::with_inverted_index(inverted);
+                let bids: Vec<Price> = (0..5).map(|i| 150_000 - i * gap).collect();
+                let asks: Vec<Price> = (0..5).map(|i| 160_000 + i * gap).collect();
+                for (&bid, &ask) in bids.iter().zip(&asks) {
+                    book.set(bid, bid as Quantity, Side::Bid);
+                    book.set(ask, ask as Quantity, Side::Ask);
+                }
+
+                for (side, prices) in [(Side::Bid, &bids), (Side::Ask, &asks)] {
+                    LEVEL_READS.with(|reads| reads.set(0));
+                    let top = book.get_top_levels(side, 10);
+                    assert_eq!(LEVEL_READS.with(Cell::get), 5, "gap {gap}");
+                    let expected: Vec<(Price, Quantity)> =
+                        prices.iter().map(|&p| (p, p as Quantity)).collect();
+                    assert_eq!(top, expected);
+
+                    LEVEL_READS.with(|reads| reads.set(0));
+                    assert_eq!(book.get_top_levels(side, 2), expected[..2]);
+                    assert_eq!(LEVEL_READS.with(Cell::get), 2);
+                }
+            }
         }
     }
 }
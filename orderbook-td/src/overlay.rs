@@ -0,0 +1,136 @@
+use crate::interfaces::{Price, Quantity, Side};
+use crate::orderbook::OrderBookImpl;
+use std::collections::BTreeMap;
+
+// ============================================================================
+// OVERLAY BOOK – IMPLIED LIQUIDITY ON TOP OF A REAL BOOK
+// ============================================================================
+
+/// Read-only view of a real book plus a sparse set of implied levels (e.g.
+/// priced off a related instrument), answering BBO and depth queries as if
+/// the implied quantity were resting. Implied size at a real price adds to
+/// it. The real book is only borrowed and never modified
+pub struct OverlayBook<'a> {
+    book: &'a OrderBookImpl,
+    implied_bids: BTreeMap<Price, Quantity>,
+    implied_asks: BTreeMap<Price, Quantity>,
+}
+
+impl<'a> OverlayBook<'a> {
+    pub fn new(book: &'a OrderBookImpl) -> Self {
+        OverlayBook {
+            book,
+            implied_bids: BTreeMap::new(),
+            implied_asks: BTreeMap::new(),
+        }
+    }
+
+    pub fn book(&self) -> &'a OrderBookImpl {
+        self.book
+    }
+
+    /// Set the implied quantity at a price; zero removes the implied level
+    pub fn set_implied(&mut self, price: Price, quantity: Quantity, side: Side) {
+        let implied = self.implied_mut(side);
+        if quantity == 0 {
+            implied.remove(&price);
+        } else {
+            implied.insert(price, quantity);
+        }
+    }
+
+    /// Drop every implied level on both sides
+    pub fn clear_implied(&mut self) {
+        self.implied_bids.clear();
+        self.implied_asks.clear();
+    }
+
+    /// Implied levels alone, best first
+    pub fn implied_levels(&self, side: Side) -> Vec<(Price, Quantity)> {
+        let implied = self.implied(side).iter().map(|(&price, &qty)| (price, qty));
+        match side {
+            Side::Bid => implied.rev().collect(),
+            Side::Ask => implied.collect(),
+        }
+    }
+
+    pub fn get_best_bid(&self) -> Option<Price> {
+        let implied = self.implied_bids.keys().next_back().copied();
+        self.book.get_best_bid().max(implied)
+    }
+
+    pub fn get_best_ask(&self) -> Option<Price> {
+        let implied = self.implied_asks.keys().next().copied();
+        match (self.book.get_best_ask(), implied) {
+            (Some(real), Some(implied)) => Some(real.min(implied)),
+            (real, implied) => real.or(implied),
+        }
+    }
+
+    /// Spread with implied levels counted; None if either side is empty
+    pub fn get_spread(&self) -> Option<Price> {
+        Some(self.get_best_ask()? - self.get_best_bid()?)
+    }
+
+    /// Real plus implied quantity at a price, saturating at `Quantity::MAX`
+    /// None if neither has any
+    pub fn get_quantity_at(&self, price: Price, side: Side) -> Option<Quantity> {
+        let real = self.book.get_quantity_at(price, side).unwrap_or(0);
+        let implied = self.implied(side).get(&price).copied().unwrap_or(0);
+        match real.saturating_add(implied) {
+            0 => None,
+            total => Some(total),
+        }
+    }
+
+    /// Top `n` levels of the merged book, best first
+    pub fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
+        let mut levels = Vec::with_capacity(n.min(64));
+        let mut real = self.book.levels(side).peekable();
+        let mut implied = self.implied_levels(side).into_iter().peekable();
+        // Bids walk down in price, asks walk up
+        let better = |a: Price, b: Price| match side {
+            Side::Bid => a > b,
+            Side::Ask => a < b,
+        };
+
+        while levels.len() < n {
+            let level = match (real.peek().copied(), implied.peek().copied()) {
+                (Some((rp, rq)), Some((ip, iq))) if rp == ip => {
+                    real.next();
+                    implied.next();
+                    (rp, rq.saturating_add(iq))
+                }
+                (Some(r), Some(i)) if better(r.0, i.0) => real.next().unwrap(),
+                (_, Some(_)) => implied.next().unwrap(),
+                (Some(_), None) => real.next().unwrap(),
+                (None, None) => break,
+            };
+            levels.push(level);
+        }
+        levels
+    }
+
+    /// Real plus implied quantity across a side, saturating at `Quantity::MAX`
+    pub fn get_total_quantity(&self, side: Side) -> Quantity {
+        self.implied(side)
+            .values()
+            .fold(self.book.get_total_quantity(side), |total, &qty| {
+                total.saturating_add(qty)
+            })
+    }
+
+    fn implied(&self, side: Side) -> &BTreeMap<Price, Quantity> {
+        match side {
+            Side::Bid => &self.implied_bids,
+            Side::Ask => &self.implied_asks,
+        }
+    }
+
+    fn implied_mut(&mut self, side: Side) -> &mut BTreeMap<Price, Quantity> {
+        match side {
+            Side::Bid => &mut self.implied_bids,
+            Side::Ask => &mut self.implied_asks,
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::interfaces::{OrderBook, Update};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// ============================================================================
+// UPDATE QUEUE – SPSC RING BUFFER BETWEEN FEED AND BOOK
+// ============================================================================
+// One feed thread pushes, one book thread drains. `tail` is written only by
+// the producer and `head` only by the consumer: the producer publishes a slot
+// with a Release store of `tail` that the consumer Acquires before reading it,
+// and the consumer hands the slot back the same way through `head`. Each end
+// also holds a claim flag for the duration of a call, so a second producer or
+// consumer racing in is turned away instead of corrupting a slot.
+
+// Keeps the two indices on separate cache lines so the ends don't false-share
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+/// Lock-free single-producer single-consumer queue of updates
+pub struct UpdateQueue {
+    slots: Box<[UnsafeCell<MaybeUninit<Update>>]>,
+    mask: usize,
+    // Next slot to read; advanced by the consumer
+    head: CachePadded<AtomicUsize>,
+    // Next slot to write; advanced by the producer
+    tail: CachePadded<AtomicUsize>,
+    pushing: AtomicBool,
+    draining: AtomicBool,
+}
+
+// Slots are only touched by the end that currently owns them per head/tail
+unsafe impl Sync for UpdateQueue {}
+
+// Releases an end's claim flag, including when `apply_update` panics mid-drain
+struct Claim<'a>(&'a AtomicBool);
+
+impl<'a> Claim<'a> {
+    fn take(flag: &'a AtomicBool) -> Option<Claim<'a>> {
+        if flag.swap(true, Ordering::Acquire) {
+            None
+        } else {
+            Some(Claim(flag))
+        }
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl UpdateQueue {
+    /// Queue holding at least `capacity` updates, rounded up to a power of two
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        UpdateQueue {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            mask: capacity - 1,
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            pushing: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Updates waiting to be drained (a snapshot; the other end may be moving)
+    pub fn len(&self) -> usize {
+        let tail = self.tail.0.load(Ordering::Acquire);
+        let head = self.head.0.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Producer side: enqueue an update. Returns false if the queue is full
+    /// (or another producer is mid-push), leaving the update unqueued
+    pub fn push(&self, update: Update) -> bool {
+        let Some(_claim) = Claim::take(&self.pushing) else {
+            return false;
+        };
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        let head = self.head.0.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.slots.len() {
+            return false;
+        }
+        unsafe { (*self.slots[tail & self.mask].get()).write(update) };
+        self.tail.0.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Consumer side: apply every update queued so far to `book`, oldest
+    /// first, and return how many were applied. Updates pushed while draining
+    /// wait for the next call. Returns 0 if another consumer is mid-drain
+    pub fn drain_into<T: OrderBook>(&self, book: &mut T) -> usize {
+        let Some(_claim) = Claim::take(&self.draining) else {
+            return 0;
+        };
+        let mut head = self.head.0.load(Ordering::Relaxed);
+        let tail = self.tail.0.load(Ordering::Acquire);
+        let count = tail.wrapping_sub(head);
+        while head != tail {
+            let update = unsafe { (*self.slots[head & self.mask].get()).assume_init_read() };
+            head = head.wrapping_add(1);
+            // Hand the slot back before applying, so a panicking update is
+            // never read twice
+            self.head.0.store(head, Ordering::Release);
+            book.apply_update(update);
+        }
+        count
+    }
+}
+
+impl Drop for UpdateQueue {
+    fn drop(&mut self) {
+        let tail = *self.tail.0.get_mut();
+        let mut head = *self.head.0.get_mut();
+        while head != tail {
+            unsafe { self.slots[head & self.mask].get_mut().assume_init_drop() };
+            head = head.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use crate::interfaces::{OrderBook, Update};
+
+// ============================================================================
+// REPLAY – EVENT-DRIVEN BACKTEST LOOP
+// ============================================================================
+
+/// Apply recorded updates to `book` in order, calling `on_event` after each
+/// one with the book and the index of the update just applied, so a strategy
+/// can read the BBO (and anything else) event by event
+pub fn replay_with_callback<T: OrderBook>(
+    updates: &[Update],
+    book: &mut T,
+    mut on_event: impl FnMut(&T, usize),
+) {
+    for (index, update) in updates.iter().enumerate() {
+        book.apply_update(update.clone());
+        on_event(book, index);
+    }
+}
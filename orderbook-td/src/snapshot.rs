@@ -0,0 +1,136 @@
+use crate::orderbook::OrderBookImpl;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// ============================================================================
+// SNAPSHOT SCHEDULER – PERIODIC ON-DISK SNAPSHOTS FOR CRASH RECOVERY
+// ============================================================================
+// Files are named snapshot-<index>.bin with a zero-padded, ever-increasing
+// index, so the newest snapshot is simply the highest index. Each file is
+// written to a temporary name and renamed into place, so a crash mid-write
+// never leaves a partial file under a snapshot name.
+
+const PREFIX: &str = "snapshot-";
+const SUFFIX: &str = ".bin";
+
+/// Writes `OrderBookImpl::to_snapshot` blobs into a directory every `every`
+/// updates and/or once per `interval`, keeping only the newest `keep` files
+pub struct SnapshotScheduler {
+    dir: PathBuf,
+    every: u64,
+    interval: Option<Duration>,
+    keep: usize,
+    since_write: u64,
+    last_write: Instant,
+    next_index: u64,
+}
+
+impl SnapshotScheduler {
+    /// `every` = 0 disables the count trigger, `interval` = None disables the
+    /// time trigger. Creates `dir` if needed and continues numbering after any
+    /// snapshots already in it
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        every: u64,
+        interval: Option<Duration>,
+        keep: usize,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let next_index = snapshot_files(&dir)?
+            .last()
+            .map_or(0, |(index, _)| index + 1);
+        Ok(SnapshotScheduler {
+            dir,
+            every,
+            interval,
+            keep: keep.max(1),
+            since_write: 0,
+            last_write: Instant::now(),
+            next_index,
+        })
+    }
+
+    /// Call after each update applied to `book`; writes a snapshot when one is
+    /// due. Returns true if a snapshot was written
+    pub fn on_update(&mut self, book: &OrderBookImpl) -> io::Result<bool> {
+        self.since_write += 1;
+
+        let count_due = self.every != 0 && self.since_write >= self.every;
+        let time_due = self
+            .interval
+            .is_some_and(|interval| self.last_write.elapsed() >= interval);
+        if !(count_due || time_due) {
+            return Ok(false);
+        }
+
+        self.write_now(book)?;
+        Ok(true)
+    }
+
+    /// Write a snapshot immediately and prune the oldest beyond `keep`
+    /// Returns the path written
+    pub fn write_now(&mut self, book: &OrderBookImpl) -> io::Result<PathBuf> {
+        let path = self
+            .dir
+            .join(format!("{PREFIX}{:020}{SUFFIX}", self.next_index));
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, book.to_snapshot())?;
+        fs::rename(&tmp, &path)?;
+
+        self.next_index += 1;
+        self.since_write = 0;
+        self.last_write = Instant::now();
+
+        let files = snapshot_files(&self.dir)?;
+        let excess = files.len().saturating_sub(self.keep);
+        for (_, old) in &files[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(path)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// Newest snapshot in `dir` that loads and verifies, skipping corrupt ones
+/// Ok(None) if the directory is missing or holds no valid snapshot
+pub fn load_latest(dir: impl AsRef<Path>) -> io::Result<Option<OrderBookImpl>> {
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        return Ok(None);
+    }
+    for (_, path) in snapshot_files(dir)?.iter().rev() {
+        let bytes = fs::read(path)?;
+        if let Ok(book) = OrderBookImpl::from_snapshot(&bytes) {
+            return Ok(Some(book));
+        }
+    }
+    Ok(None)
+}
+
+// Snapshot files in `dir` sorted by index, oldest first
+fn snapshot_files(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| {
+                name.strip_prefix(PREFIX)?
+                    .strip_suffix(SUFFIX)?
+                    .parse()
+                    .ok()
+            });
+        if let Some(index) = index {
+            files.push((index, path));
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
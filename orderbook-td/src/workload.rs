@@ -0,0 +1,192 @@
+use crate::interfaces::{OrderBook, Price, Quantity, Side, Update};
+
+// ============================================================================
+// WORKLOAD GENERATOR – REALISTIC BOOK SHAPES FOR BENCHMARKS
+// ============================================================================
+
+const MID_PRICE: Price = 100_000;
+const PRICE_LIMIT: Price = 200_000; // keep generated prices inside the dense array
+const MAX_GAP: f64 = 10_000.0; // cap power-law gaps so a single draw can't leave the range
+
+/// Small deterministic xorshift64* generator, good enough for workloads
+#[derive(Debug, Clone)]
+pub struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift
+        XorShift64 {
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform float in (0, 1]
+    #[inline]
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in [low, high]
+    #[inline]
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next_u64() % (high - low + 1)
+    }
+}
+
+/// How populated levels are spaced moving away from the touch
+#[derive(Debug, Clone, Copy)]
+pub enum LevelSpacing {
+    /// Every tick is populated
+    Dense,
+    /// Gaps drawn from a geometric distribution with the given mean (in ticks)
+    Geometric { mean_gap: f64 },
+    /// Gaps drawn from a Pareto distribution with shape `alpha` (min gap 1 tick)
+    /// Smaller alpha means heavier tails: most levels hug the touch, a few sit far away
+    PowerLaw { alpha: f64 },
+}
+
+/// Generates a book of a given shape plus a stream of updates against it
+pub struct WorkloadGenerator {
+    rng: XorShift64,
+    spacing: LevelSpacing,
+    bid_levels: Vec<Price>,
+    ask_levels: Vec<Price>,
+    pending: Option<Update>,
+}
+
+impl WorkloadGenerator {
+    pub fn new(seed: u64, levels_per_side: usize, spacing: LevelSpacing) -> Self {
+        let mut generator = WorkloadGenerator {
+            rng: XorShift64::new(seed),
+            spacing,
+            bid_levels: Vec::with_capacity(levels_per_side),
+            ask_levels: Vec::with_capacity(levels_per_side),
+            pending: None,
+        };
+
+        let mut bid = MID_PRICE - 1;
+        let mut ask = MID_PRICE + 1;
+        for _ in 0..levels_per_side {
+            if bid >= 0 {
+                generator.bid_levels.push(bid);
+                bid -= generator.next_gap();
+            }
+            if ask < PRICE_LIMIT {
+                generator.ask_levels.push(ask);
+                ask += generator.next_gap();
+            }
+        }
+
+        generator
+    }
+
+    fn next_gap(&mut self) -> Price {
+        match self.spacing {
+            LevelSpacing::Dense => 1,
+            LevelSpacing::Geometric { mean_gap } => {
+                let p = 1.0 / mean_gap.max(1.0);
+                if p >= 1.0 {
+                    1
+                } else {
+                    1 + (self.rng.next_f64().ln() / (1.0 - p).ln()) as Price
+                }
+            }
+            LevelSpacing::PowerLaw { alpha } => {
+                let gap = self.rng.next_f64().powf(-1.0 / alpha);
+                gap.min(MAX_GAP) as Price
+            }
+        }
+    }
+
+    fn next_quantity(&mut self) -> Quantity {
+        self.rng.range(1, 1000)
+    }
+
+    /// Populated prices on a side, best first
+    pub fn levels(&self, side: Side) -> &[Price] {
+        match side {
+            Side::Bid => &self.bid_levels,
+            Side::Ask => &self.ask_levels,
+        }
+    }
+
+    /// Load the generated book shape into an empty order book
+    pub fn populate<T: OrderBook>(&mut self, ob: &mut T) {
+        for i in 0..self.bid_levels.len() {
+            let quantity = self.next_quantity();
+            ob.apply_update(Update::Set {
+                price: self.bid_levels[i],
+                quantity,
+                side: Side::Bid,
+            });
+        }
+        for i in 0..self.ask_levels.len() {
+            let quantity = self.next_quantity();
+            ob.apply_update(Update::Set {
+                price: self.ask_levels[i],
+                quantity,
+                side: Side::Ask,
+            });
+        }
+    }
+
+    /// Next update in the stream. Most updates resize an existing level, biased
+    /// toward the touch; some pull the best level and restore it on the next
+    /// update, which forces a best-price rescan across whatever gap sits behind it
+    pub fn next_update(&mut self) -> Update {
+        if let Some(update) = self.pending.take() {
+            return update;
+        }
+
+        let side = if self.rng.next_u64() & 1 == 0 {
+            Side::Bid
+        } else {
+            Side::Ask
+        };
+        let count = self.levels(side).len();
+        if count == 0 {
+            return Update::Remove { price: 0, side };
+        }
+
+        if self.rng.range(0, 99) < 15 {
+            let price = self.levels(side)[0];
+            let quantity = self.next_quantity();
+            self.pending = Some(Update::Set {
+                price,
+                quantity,
+                side,
+            });
+            return Update::Remove { price, side };
+        }
+
+        // Rank ~ |log-uniform|, so near-touch levels see most of the traffic
+        let rank = ((count as f64).powf(self.rng.next_f64()) as usize - 1).min(count - 1);
+        let price = self.levels(side)[rank];
+        let quantity = self.next_quantity();
+        Update::Set {
+            price,
+            quantity,
+            side,
+        }
+    }
+
+    /// Fill `out` with the next `count` updates, reusing its allocation
+    pub fn fill_updates(&mut self, out: &mut Vec<Update>, count: usize) {
+        out.clear();
+        for _ in 0..count {
+            out.push(self.next_update());
+        }
+    }
+}